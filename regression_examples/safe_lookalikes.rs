@@ -0,0 +1,26 @@
+// safe calls that share their names with unsafe std APIs, inside an unsafe
+// fn. only two are calls to unsafe fns, the `ptr::read` and the call to
+// `lookalikes` itself, which the walker's tests hold it to.
+
+use std::ops::Add;
+use std::sync::RwLock;
+
+unsafe fn lookalikes(lock: &RwLock<u8>, opt: Option<u8>, a: u8, b: u8, p: *const u8) -> u8 {
+    let read = lock.read().is_ok();
+    let written = lock.write().is_ok();
+    let _ = opt.as_ref();
+    let sum = a.add(b);
+    if read && written {
+        sum + std::ptr::read(p)
+    } else {
+        sum
+    }
+}
+
+fn main() {
+    let lock = RwLock::new(1);
+    let x = 2;
+    unsafe {
+        lookalikes(&lock, Some(1), 1, 2, &x);
+    }
+}
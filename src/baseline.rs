@@ -0,0 +1,276 @@
+use serde_derive::Deserialize;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+
+use crate::json::SCHEMA_VERSION;
+use crate::Result;
+
+/// a report saved earlier with `--format json` or `baseline save`, to compare
+/// the current one against. only the fields needed for that are read, and a
+/// searched directory is read as a package named after its relative path with
+/// no version
+#[derive(Deserialize)]
+pub struct Baseline {
+    pub schema_version: u32,
+    pub packages: Vec<Package>,
+    #[serde(default)]
+    directory: Option<Directory>,
+}
+
+#[derive(Deserialize)]
+pub struct Package {
+    pub name: String,
+    pub version: String,
+    /// what the files of its sites are relative to. reports from before it
+    /// was added don't have it, and their sites keep their whole path
+    #[serde(default)]
+    pub root: String,
+    pub unsafe_lines: usize,
+    #[serde(default)]
+    pub unsafe_declarations: usize,
+    #[serde(default)]
+    pub categories: BTreeMap<String, usize>,
+    #[serde(default)]
+    pub targets: Vec<Target>,
+}
+
+#[derive(Deserialize)]
+pub struct Target {
+    #[serde(default)]
+    pub sites: Vec<Site>,
+}
+
+#[derive(Deserialize)]
+pub struct Site {
+    #[serde(rename = "type")]
+    pub site_type: String,
+    #[serde(default)]
+    pub declaration: Option<String>,
+    #[serde(default)]
+    pub category: Option<String>,
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    #[serde(default)]
+    pub snippet: String,
+}
+
+#[derive(Deserialize)]
+struct Directory {
+    path: String,
+    /// reports from before it was added are matched by their whole path
+    #[serde(default)]
+    relative_path: Option<String>,
+    unsafe_lines: usize,
+    #[serde(default)]
+    unsafe_declarations: usize,
+    #[serde(default)]
+    categories: BTreeMap<String, usize>,
+    #[serde(default)]
+    sites: Vec<Site>,
+}
+
+/// `file` relative to `root`, or all of it when it isn't under it
+fn relative<'a>(file: &'a str, root: &str) -> &'a Path {
+    Path::new(file)
+        .strip_prefix(root)
+        .unwrap_or_else(|_| Path::new(file))
+}
+
+impl Site {
+    /// e.g. `deref`, `unsafe fn declaration` or `unsafe line`
+    fn label(&self) -> String {
+        match (&self.declaration, &self.category) {
+            (Some(declaration), _) => format!("unsafe {} declaration", declaration),
+            (_, Some(category)) => category.clone(),
+            _ => format!("unsafe {}", self.site_type),
+        }
+    }
+
+    /// what a site is told apart by, leaving out where exactly in its file it
+    /// is so that code moving around doesn't count as a change
+    fn key(&self, root: &str) -> (String, String, String) {
+        (
+            self.label(),
+            relative(&self.file, root).display().to_string(),
+            self.snippet.clone(),
+        )
+    }
+}
+
+impl Package {
+    /// e.g. `itoa v0.4.3`, or just the path of a directory
+    fn title(&self) -> String {
+        if self.version.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{} v{}", self.name, self.version)
+        }
+    }
+
+    /// the unsafe lines, declarations and operations per category
+    fn counts(&self) -> BTreeMap<String, usize> {
+        let mut counts = self.categories.clone();
+        counts.insert("unsafe_lines".to_string(), self.unsafe_lines);
+        counts.insert("unsafe_declarations".to_string(), self.unsafe_declarations);
+        counts
+    }
+
+    fn sites(&self) -> impl Iterator<Item = &Site> {
+        self.targets.iter().flat_map(|target| target.sites.iter())
+    }
+
+    /// the sites that aren't in `old`, or all of them for a new package
+    fn new_sites(&self, old: Option<&Package>) -> Vec<&Site> {
+        let mut old_sites: HashMap<(String, String, String), usize> = HashMap::new();
+        if let Some(old) = old {
+            for site in old.sites() {
+                *old_sites.entry(site.key(&old.root)).or_insert(0) += 1;
+            }
+        }
+        let mut new = vec![];
+        for site in self.sites() {
+            match old_sites.get_mut(&site.key(&self.root)) {
+                Some(count) if *count > 0 => *count -= 1,
+                _ => new.push(site),
+            }
+        }
+        new
+    }
+}
+
+impl Baseline {
+    pub fn load(path: &Path) -> Result<Baseline> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("couldn't read {}: {}", path.display(), e))?;
+        Baseline::parse(&text).map_err(|e| {
+            format!("{} isn't a cargo-danger json report: {}", path.display(), e).into()
+        })
+    }
+
+    /// reads a report made by `--format json`
+    pub fn parse(text: &str) -> Result<Baseline> {
+        let mut baseline: Baseline = serde_json::from_str(text)?;
+        if baseline.schema_version > SCHEMA_VERSION {
+            return Err(format!(
+                "it has schema version {}, but this cargo-danger only knows up to {}",
+                baseline.schema_version, SCHEMA_VERSION
+            )
+            .into());
+        }
+        if let Some(mut directory) = baseline.directory.take() {
+            baseline.packages.push(Package {
+                name: directory
+                    .relative_path
+                    .take()
+                    .unwrap_or_else(|| directory.path.clone()),
+                root: directory.path,
+                version: String::new(),
+                unsafe_lines: directory.unsafe_lines,
+                unsafe_declarations: directory.unsafe_declarations,
+                categories: directory.categories,
+                targets: vec![Target {
+                    sites: directory.sites,
+                }],
+            });
+        }
+        Ok(baseline)
+    }
+
+    /// the package with this name and version, or the only package with this
+    /// name when the version changed since
+    pub fn package(&self, name: &str, version: &str) -> Option<&Package> {
+        let exact = self
+            .packages
+            .iter()
+            .find(|p| p.name == name && p.version == version);
+        if exact.is_some() {
+            return exact;
+        }
+        let mut named = self.packages.iter().filter(|p| p.name == name);
+        match (named.next(), named.next()) {
+            (Some(package), None) => Some(package),
+            _ => None,
+        }
+    }
+
+    /// the packages of the baseline that none of `present` match, as
+    /// `(name, version)`
+    pub fn gone<'a>(&'a self, present: &[(String, String)]) -> Vec<&'a Package> {
+        self.packages
+            .iter()
+            .filter(|p| {
+                !present.iter().any(|(name, version)| {
+                    self.package(name, version)
+                        .is_some_and(|matched| std::ptr::eq(matched, *p))
+                })
+            })
+            .collect()
+    }
+
+    /// prints every package of `current` with more of any count than the
+    /// baseline has, or with any unsafe code when the baseline doesn't have
+    /// it, along with the sites that weren't there before. the packages that
+    /// went down or are gone get a line too. true when nothing grew
+    pub fn check(&self, current: &Baseline) -> bool {
+        let mut grew = false;
+        let mut went_down = false;
+        for package in &current.packages {
+            let old = self.package(&package.name, &package.version);
+            let old_counts = old.map(Package::counts).unwrap_or_default();
+            let counts = package.counts();
+            let names: BTreeSet<&String> = counts.keys().chain(old_counts.keys()).collect();
+            let mut up = vec![];
+            let mut down = vec![];
+            for name in names {
+                let was = old_counts.get(name).cloned().unwrap_or(0);
+                let is = counts.get(name).cloned().unwrap_or(0);
+                if is > was {
+                    up.push(format!("{} {} -> {}", name, was, is));
+                } else if is < was {
+                    down.push(format!("{} {} -> {}", name, was, is));
+                }
+            }
+            let was = match old {
+                Some(old) if old.version != package.version => format!(" (was v{})", old.version),
+                _ => String::new(),
+            };
+            if !up.is_empty() {
+                grew = true;
+                let change = if old.is_some() { "grew" } else { "is new" };
+                println!("{}{} {}: {}", package.title(), was, change, up.join(", "));
+                for site in package.new_sites(old) {
+                    println!(
+                        "  {}:{}:{} {}",
+                        relative(&site.file, &package.root).display(),
+                        site.line,
+                        site.column,
+                        site.label()
+                    );
+                    println!("    {}", site.snippet);
+                }
+            } else if !down.is_empty() {
+                went_down = true;
+                println!("{}{} went down: {}", package.title(), was, down.join(", "));
+            }
+        }
+        let present: Vec<(String, String)> = current
+            .packages
+            .iter()
+            .map(|p| (p.name.clone(), p.version.clone()))
+            .collect();
+        for package in self.gone(&present) {
+            went_down = true;
+            println!("{} is gone", package.title());
+        }
+        if !grew {
+            println!("no unsafe code was added since the baseline");
+            if went_down {
+                println!("save the baseline again to lock in what went down");
+            }
+        }
+        !grew
+    }
+}
@@ -0,0 +1,348 @@
+use cargo::util::{Cfg, CfgExpr};
+use cargo::Config;
+use quote::ToTokens;
+use syn::visit_mut::{self, VisitMut};
+use syn::{
+    Attribute, Block, Expr, ExprMatch, ForeignItem, ImplItem, Item, ItemForeignMod, ItemImpl,
+    ItemMod, ItemTrait, Meta, NestedMeta, Stmt, TraitItem,
+};
+
+use std::process::Command;
+use std::str::FromStr;
+
+use crate::Result;
+
+/// the cfg values code gets compiled with: a target's, as `rustc --print cfg`
+/// gives them for a release build, plus whatever features are turned on
+#[derive(Debug, Clone)]
+pub struct CfgSet {
+    cfgs: Vec<Cfg>,
+}
+
+impl CfgSet {
+    /// the cfg values of `target`, or of the host when there isn't one
+    pub fn for_target(config: &Config, target: Option<&str>) -> Result<CfgSet> {
+        let rustc = config.rustc(None)?;
+        let mut command = Command::new(&rustc.path);
+        // -O, because debug_assertions are off in release builds
+        command.arg("--print=cfg").arg("-O");
+        if let Some(target) = target {
+            command.arg("--target").arg(target);
+        }
+        let output = command.output()?;
+        if !output.status.success() {
+            return Err(format!(
+                "couldn't get the cfg values of {}: {}",
+                target.unwrap_or(&rustc.host),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+        let mut cfgs = vec![];
+        for line in String::from_utf8(output.stdout)?.lines() {
+            cfgs.push(Cfg::from_str(line)?);
+        }
+        Ok(CfgSet { cfgs })
+    }
+
+    pub fn with_features<S: AsRef<str>>(&self, features: &[S]) -> CfgSet {
+        let mut cfgs = self.cfgs.clone();
+        for feature in features {
+            cfgs.push(Cfg::KeyPair(
+                "feature".to_string(),
+                feature.as_ref().to_string(),
+            ));
+        }
+        CfgSet { cfgs }
+    }
+
+    /// as for tests and benches, which get built with `--test`
+    pub fn with_test(&self) -> CfgSet {
+        let mut cfgs = self.cfgs.clone();
+        cfgs.push(Cfg::Name("test".to_string()));
+        CfgSet { cfgs }
+    }
+
+    /// whether a cfg predicate like `all(unix, feature = "std")` holds.
+    /// predicates we can't make sense of are taken to hold, so nothing gets
+    /// dropped by mistake
+    fn holds(&self, predicate: &Meta) -> bool {
+        let predicate = predicate.into_token_stream().to_string();
+        CfgExpr::from_str(&predicate)
+            .map(|expr| expr.matches(&self.cfgs))
+            .unwrap_or(true)
+    }
+
+    /// replace every `#[cfg_attr(predicate, attrs..)]` with its attrs if the
+    /// predicate holds, or nothing if it doesn't, then tell whether the `#[cfg]`
+    /// attributes left all hold
+    fn expand(&self, attrs: &mut Vec<Attribute>) -> bool {
+        let mut expanded = Vec::with_capacity(attrs.len());
+        for attr in attrs.drain(..) {
+            match cfg_attr(&attr) {
+                Some((predicate, inner)) => {
+                    if self.holds(&predicate) {
+                        expanded.extend(inner.into_iter().map(|meta| Attribute {
+                            tts: meta_tts(&meta),
+                            path: meta.name().into(),
+                            ..attr.clone()
+                        }));
+                    }
+                }
+                None => expanded.push(attr),
+            }
+        }
+        *attrs = expanded;
+        attrs
+            .iter()
+            .filter_map(cfg_predicate)
+            .all(|predicate| self.holds(&predicate))
+    }
+
+    fn retain<T: HasAttrs>(&self, nodes: &mut Vec<T>) {
+        let mut kept = Vec::with_capacity(nodes.len());
+        for mut node in nodes.drain(..) {
+            let holds = match node.attrs_mut() {
+                Some(attrs) => self.expand(attrs),
+                None => true,
+            };
+            if holds {
+                kept.push(node);
+            }
+        }
+        *nodes = kept;
+    }
+
+    /// drop everything from `file` that isn't compiled with these cfg values:
+    /// items, statements and match arms under a `#[cfg]` that doesn't hold,
+    /// `mod` declarations included. `#[cfg_attr]`s get expanded along the way,
+    /// so a `#[cfg_attr(windows, path = "windows.rs")]` turns into a `#[path]`
+    pub fn prune(&self, file: &mut syn::File) {
+        if !self.expand(&mut file.attrs) {
+            file.items.clear();
+            return;
+        }
+        Pruner { cfg: self }.visit_file_mut(file);
+    }
+}
+
+/// the predicate of a `#[cfg(predicate)]`
+fn cfg_predicate(attr: &Attribute) -> Option<Meta> {
+    match attr.interpret_meta() {
+        Some(Meta::List(ref list)) if list.ident == "cfg" && list.nested.len() == 1 => {
+            match list.nested.first().map(|pair| pair.into_value()) {
+                Some(NestedMeta::Meta(predicate)) => Some(predicate.clone()),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// the predicate and attributes of a `#[cfg_attr(predicate, attrs..)]`
+fn cfg_attr(attr: &Attribute) -> Option<(Meta, Vec<Meta>)> {
+    let list = match attr.interpret_meta() {
+        Some(Meta::List(list)) => list,
+        _ => return None,
+    };
+    if list.ident != "cfg_attr" {
+        return None;
+    }
+    let mut nested = list.nested.into_iter().map(|nested| match nested {
+        NestedMeta::Meta(meta) => Some(meta),
+        NestedMeta::Literal(_) => None,
+    });
+    let predicate = nested.next()??;
+    let inner: Option<Vec<Meta>> = nested.collect();
+    Some((predicate, inner?))
+}
+
+/// what follows the path of an attribute: `(a, b)` for a list, `= "..."` for a
+/// name-value pair, nothing for a bare word
+fn meta_tts(meta: &Meta) -> proc_macro2::TokenStream {
+    let mut tts = proc_macro2::TokenStream::new();
+    match meta {
+        Meta::Word(_) => {}
+        Meta::List(list) => list
+            .paren_token
+            .surround(&mut tts, |tts| list.nested.to_tokens(tts)),
+        Meta::NameValue(name_value) => {
+            name_value.eq_token.to_tokens(&mut tts);
+            name_value.lit.to_tokens(&mut tts);
+        }
+    }
+    tts
+}
+
+struct Pruner<'a> {
+    cfg: &'a CfgSet,
+}
+
+impl<'a> VisitMut for Pruner<'a> {
+    fn visit_file_mut(&mut self, file: &mut syn::File) {
+        self.cfg.retain(&mut file.items);
+        visit_mut::visit_file_mut(self, file);
+    }
+
+    fn visit_item_mod_mut(&mut self, mod_def: &mut ItemMod) {
+        if let Some((_, items)) = &mut mod_def.content {
+            self.cfg.retain(items);
+        }
+        visit_mut::visit_item_mod_mut(self, mod_def);
+    }
+
+    fn visit_item_impl_mut(&mut self, impl_def: &mut ItemImpl) {
+        self.cfg.retain(&mut impl_def.items);
+        visit_mut::visit_item_impl_mut(self, impl_def);
+    }
+
+    fn visit_item_trait_mut(&mut self, trait_def: &mut ItemTrait) {
+        self.cfg.retain(&mut trait_def.items);
+        visit_mut::visit_item_trait_mut(self, trait_def);
+    }
+
+    fn visit_item_foreign_mod_mut(&mut self, foreign_mod: &mut ItemForeignMod) {
+        self.cfg.retain(&mut foreign_mod.items);
+        visit_mut::visit_item_foreign_mod_mut(self, foreign_mod);
+    }
+
+    fn visit_block_mut(&mut self, block: &mut Block) {
+        self.cfg.retain(&mut block.stmts);
+        visit_mut::visit_block_mut(self, block);
+    }
+
+    fn visit_expr_match_mut(&mut self, match_expr: &mut ExprMatch) {
+        self.cfg.retain(&mut match_expr.arms);
+        visit_mut::visit_expr_match_mut(self, match_expr);
+    }
+}
+
+/// the things `#[cfg]` can remove from a list of them
+trait HasAttrs {
+    fn attrs_mut(&mut self) -> Option<&mut Vec<Attribute>>;
+}
+
+impl HasAttrs for Item {
+    fn attrs_mut(&mut self) -> Option<&mut Vec<Attribute>> {
+        match self {
+            Item::ExternCrate(item) => Some(&mut item.attrs),
+            Item::Use(item) => Some(&mut item.attrs),
+            Item::Static(item) => Some(&mut item.attrs),
+            Item::Const(item) => Some(&mut item.attrs),
+            Item::Fn(item) => Some(&mut item.attrs),
+            Item::Mod(item) => Some(&mut item.attrs),
+            Item::ForeignMod(item) => Some(&mut item.attrs),
+            Item::Type(item) => Some(&mut item.attrs),
+            Item::Existential(item) => Some(&mut item.attrs),
+            Item::Struct(item) => Some(&mut item.attrs),
+            Item::Enum(item) => Some(&mut item.attrs),
+            Item::Union(item) => Some(&mut item.attrs),
+            Item::Trait(item) => Some(&mut item.attrs),
+            Item::TraitAlias(item) => Some(&mut item.attrs),
+            Item::Impl(item) => Some(&mut item.attrs),
+            Item::Macro(item) => Some(&mut item.attrs),
+            Item::Macro2(item) => Some(&mut item.attrs),
+            Item::Verbatim(_) => None,
+        }
+    }
+}
+
+impl HasAttrs for ImplItem {
+    fn attrs_mut(&mut self) -> Option<&mut Vec<Attribute>> {
+        match self {
+            ImplItem::Const(item) => Some(&mut item.attrs),
+            ImplItem::Method(item) => Some(&mut item.attrs),
+            ImplItem::Type(item) => Some(&mut item.attrs),
+            ImplItem::Existential(item) => Some(&mut item.attrs),
+            ImplItem::Macro(item) => Some(&mut item.attrs),
+            ImplItem::Verbatim(_) => None,
+        }
+    }
+}
+
+impl HasAttrs for TraitItem {
+    fn attrs_mut(&mut self) -> Option<&mut Vec<Attribute>> {
+        match self {
+            TraitItem::Const(item) => Some(&mut item.attrs),
+            TraitItem::Method(item) => Some(&mut item.attrs),
+            TraitItem::Type(item) => Some(&mut item.attrs),
+            TraitItem::Macro(item) => Some(&mut item.attrs),
+            TraitItem::Verbatim(_) => None,
+        }
+    }
+}
+
+impl HasAttrs for ForeignItem {
+    fn attrs_mut(&mut self) -> Option<&mut Vec<Attribute>> {
+        match self {
+            ForeignItem::Fn(item) => Some(&mut item.attrs),
+            ForeignItem::Static(item) => Some(&mut item.attrs),
+            ForeignItem::Type(item) => Some(&mut item.attrs),
+            ForeignItem::Macro(item) => Some(&mut item.attrs),
+            ForeignItem::Verbatim(_) => None,
+        }
+    }
+}
+
+impl HasAttrs for syn::Arm {
+    fn attrs_mut(&mut self) -> Option<&mut Vec<Attribute>> {
+        Some(&mut self.attrs)
+    }
+}
+
+impl HasAttrs for Stmt {
+    fn attrs_mut(&mut self) -> Option<&mut Vec<Attribute>> {
+        match self {
+            Stmt::Local(local) => Some(&mut local.attrs),
+            Stmt::Item(item) => item.attrs_mut(),
+            Stmt::Expr(expr) | Stmt::Semi(expr, _) => expr.attrs_mut(),
+        }
+    }
+}
+
+impl HasAttrs for Expr {
+    fn attrs_mut(&mut self) -> Option<&mut Vec<Attribute>> {
+        match self {
+            Expr::Box(expr) => Some(&mut expr.attrs),
+            Expr::InPlace(expr) => Some(&mut expr.attrs),
+            Expr::Array(expr) => Some(&mut expr.attrs),
+            Expr::Call(expr) => Some(&mut expr.attrs),
+            Expr::MethodCall(expr) => Some(&mut expr.attrs),
+            Expr::Tuple(expr) => Some(&mut expr.attrs),
+            Expr::Binary(expr) => Some(&mut expr.attrs),
+            Expr::Unary(expr) => Some(&mut expr.attrs),
+            Expr::Lit(expr) => Some(&mut expr.attrs),
+            Expr::Cast(expr) => Some(&mut expr.attrs),
+            Expr::Type(expr) => Some(&mut expr.attrs),
+            Expr::Let(expr) => Some(&mut expr.attrs),
+            Expr::If(expr) => Some(&mut expr.attrs),
+            Expr::While(expr) => Some(&mut expr.attrs),
+            Expr::ForLoop(expr) => Some(&mut expr.attrs),
+            Expr::Loop(expr) => Some(&mut expr.attrs),
+            Expr::Match(expr) => Some(&mut expr.attrs),
+            Expr::Closure(expr) => Some(&mut expr.attrs),
+            Expr::Unsafe(expr) => Some(&mut expr.attrs),
+            Expr::Block(expr) => Some(&mut expr.attrs),
+            Expr::Assign(expr) => Some(&mut expr.attrs),
+            Expr::AssignOp(expr) => Some(&mut expr.attrs),
+            Expr::Field(expr) => Some(&mut expr.attrs),
+            Expr::Index(expr) => Some(&mut expr.attrs),
+            Expr::Range(expr) => Some(&mut expr.attrs),
+            Expr::Path(expr) => Some(&mut expr.attrs),
+            Expr::Reference(expr) => Some(&mut expr.attrs),
+            Expr::Break(expr) => Some(&mut expr.attrs),
+            Expr::Continue(expr) => Some(&mut expr.attrs),
+            Expr::Return(expr) => Some(&mut expr.attrs),
+            Expr::Macro(expr) => Some(&mut expr.attrs),
+            Expr::Struct(expr) => Some(&mut expr.attrs),
+            Expr::Repeat(expr) => Some(&mut expr.attrs),
+            Expr::Paren(expr) => Some(&mut expr.attrs),
+            Expr::Group(expr) => Some(&mut expr.attrs),
+            Expr::Try(expr) => Some(&mut expr.attrs),
+            Expr::Async(expr) => Some(&mut expr.attrs),
+            Expr::TryBlock(expr) => Some(&mut expr.attrs),
+            Expr::Yield(expr) => Some(&mut expr.attrs),
+            Expr::Verbatim(_) => None,
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use proc_macro2::LineColumn;
+
+use crate::ffi::{FfiKind, FfiSite};
+use crate::walker::{Category, DeclarationKind, Fingerprint, Location, UnsafeSites};
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// a single unsafe site, pointing back at the code it came from so it can be
+/// looked up without grepping for it
+#[derive(Debug, Clone)]
+pub struct CodeLine {
+    pub file_path: PathBuf,
+    /// 1-based, like an editor shows it
+    pub line_number: usize,
+    /// 1-based, like an editor shows it
+    pub column: usize,
+    pub end_line_number: usize,
+    pub end_column: usize,
+    /// every source line the site touches, untrimmed
+    pub raw_line: String,
+    /// there for everything the walker finds, but not for the FFI surface
+    pub fingerprint: Option<Fingerprint>,
+}
+
+impl CodeLine {
+    /// `start` and `end` come straight from proc_macro2, so lines are 1-based
+    /// but columns are 0-based
+    pub fn new(file_path: &Path, start: LineColumn, end: LineColumn, source: &str) -> CodeLine {
+        let raw_line = source
+            .lines()
+            .skip(start.line.saturating_sub(1))
+            .take(end.line + 1 - start.line)
+            .collect::<Vec<_>>()
+            .join("\n");
+        CodeLine {
+            file_path: file_path.to_path_buf(),
+            line_number: start.line,
+            column: start.column + 1,
+            end_line_number: end.line,
+            end_column: end.column + 1,
+            raw_line,
+            fingerprint: None,
+        }
+    }
+
+    fn with_fingerprint(self, fingerprint: Fingerprint) -> CodeLine {
+        CodeLine {
+            fingerprint: Some(fingerprint),
+            ..self
+        }
+    }
+
+    /// just the code between the start and end of the site, with whitespace
+    /// squashed so multi-line signatures fit on one line
+    pub fn snippet(&self) -> String {
+        let last = self.end_line_number - self.line_number;
+        let mut text = vec![];
+        for (i, line) in self.raw_line.lines().enumerate() {
+            let mut chars: Vec<char> = line.chars().collect();
+            if i == last {
+                chars.truncate(self.end_column - 1);
+            }
+            if i == 0 {
+                chars = chars.split_off((self.column - 1).min(chars.len()));
+            }
+            text.push(chars.into_iter().collect::<String>());
+        }
+        text.join(" ")
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// an item that is itself marked `unsafe`
+#[derive(Debug, Clone)]
+pub struct Declaration {
+    pub kind: DeclarationKind,
+    pub code: CodeLine,
+}
+
+/// an operation that needs `unsafe`, like a raw pointer dereference
+#[derive(Debug, Clone)]
+pub struct Operation {
+    pub category: Category,
+    pub code: CodeLine,
+}
+
+/// part of a crate's FFI surface, see `FfiKind`
+#[derive(Debug, Clone)]
+pub struct Ffi {
+    pub kind: FfiKind,
+    pub abi: Option<String>,
+    pub name: String,
+    pub code: CodeLine,
+}
+
+/// the sites of the code of several targets, with those of a file the targets
+/// share only taken once
+pub fn unique_sites<'a, I>(code: I) -> Vec<(String, &'a CodeLine)>
+where
+    I: IntoIterator<Item = &'a UnsafeCode>,
+{
+    let mut seen = HashSet::new();
+    let mut sites = vec![];
+    for code in code {
+        for (label, line) in code.sites() {
+            let at = (
+                label.clone(),
+                line.file_path.clone(),
+                line.line_number,
+                line.column,
+            );
+            if seen.insert(at) {
+                sites.push((label, line));
+            }
+        }
+    }
+    sites
+}
+
+/// everything unsafe found in a set of files, with unsafe declarations kept
+/// apart from the lines of unsafe code in bodies
+#[derive(Debug, Default)]
+pub struct UnsafeCode {
+    pub lines: Vec<CodeLine>,
+    pub declarations: Vec<Declaration>,
+    pub operations: Vec<Operation>,
+    pub ffi: Vec<Ffi>,
+}
+
+impl UnsafeCode {
+    /// resolve what the walker found in `file_path` against its source
+    pub fn add_sites(&mut self, file_path: &Path, sites: UnsafeSites, source: &str) {
+        let code = |location: Location, fingerprint| {
+            CodeLine::new(file_path, location.start, location.end, source)
+                .with_fingerprint(fingerprint)
+        };
+        for (location, fingerprint) in sites.lines {
+            self.lines.push(code(location, fingerprint));
+        }
+        for (kind, location, fingerprint) in sites.declarations {
+            self.declarations.push(Declaration {
+                kind,
+                code: code(location, fingerprint),
+            });
+        }
+        for (category, location, fingerprint) in sites.operations {
+            self.operations.push(Operation {
+                category,
+                code: code(location, fingerprint),
+            });
+        }
+    }
+
+    pub fn add_ffi_sites(&mut self, file_path: &Path, sites: Vec<FfiSite>, source: &str) {
+        for site in sites {
+            self.ffi.push(Ffi {
+                kind: site.kind,
+                abi: site.abi,
+                name: site.name,
+                code: CodeLine::new(file_path, site.location.start, site.location.end, source),
+            });
+        }
+    }
+
+    /// the declarations, operations and lines, labelled like `-v` does. an
+    /// `unsafe impl` only shows up once, as the operation
+    pub fn sites(&self) -> Vec<(String, &CodeLine)> {
+        let mut sites = vec![];
+        for declaration in &self.declarations {
+            if declaration.kind != DeclarationKind::Impl {
+                let label = format!("unsafe {} declaration", declaration.kind.name());
+                sites.push((label, &declaration.code));
+            }
+        }
+        for operation in &self.operations {
+            sites.push((operation.category.name().to_string(), &operation.code));
+        }
+        for line in &self.lines {
+            sites.push(("unsafe line".to_string(), line));
+        }
+        sites
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty() && self.declarations.is_empty() && self.operations.is_empty()
+    }
+
+    /// how many operations of each category there are, leaving out the ones
+    /// that never came up
+    pub fn category_counts(&self) -> BTreeMap<Category, usize> {
+        let mut counts = BTreeMap::new();
+        for operation in &self.operations {
+            *counts.entry(operation.category).or_insert(0) += 1;
+        }
+        counts
+    }
+}
@@ -0,0 +1,266 @@
+use cargo::core::SourceId;
+use cargo::ops::read_package;
+use cargo::Config;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cfg::CfgSet;
+use crate::code_line::{unique_sites, CodeLine, UnsafeCode};
+use crate::targets::{built_targets, TargetKind};
+use crate::{package_files, unsafe_code_of_package, PackageCfg, Result};
+
+/// one version of a crate, with its unsafe code
+pub struct Version {
+    pub version: String,
+    /// the directory holding its Cargo.toml
+    pub root: PathBuf,
+    pub targets: BTreeMap<TargetKind, UnsafeCode>,
+}
+
+/// where cargo unpacked `name` at `version` from whichever registry it came
+/// from
+pub fn cached_source(config: &Config, name: &str, version: &str) -> Result<PathBuf> {
+    let cache = config.registry_source_path().into_path_unlocked();
+    let dir_name = format!("{}-{}", name, version);
+    if cache.is_dir() {
+        for registry in fs::read_dir(&cache)? {
+            let dir = registry?.path().join(&dir_name);
+            if dir.join("Cargo.toml").is_file() {
+                return Ok(dir);
+            }
+        }
+    }
+    Err(format!(
+        "{} v{} isn't in the registry source cache at {}, cargo fetch a project that depends on it first",
+        name,
+        version,
+        cache.display()
+    )
+    .into())
+}
+
+/// `name` at `version` from the registry source cache, with every feature
+/// turned on so that nothing behind one gets left out
+pub fn cached_version(
+    config: &Config,
+    name: &str,
+    version: &str,
+    target: Option<&str>,
+) -> Result<Version> {
+    let root = cached_source(config, name, version)?;
+    let (package, _) = read_package(
+        &root.join("Cargo.toml"),
+        &SourceId::for_path(&root)?,
+        config,
+    )?;
+    let mut features: Vec<String> = package
+        .summary()
+        .features()
+        .keys()
+        .map(|feature| feature.to_string())
+        .collect();
+    // optional dependencies are features too
+    features.extend(
+        package
+            .dependencies()
+            .iter()
+            .filter(|dep| dep.is_optional())
+            .map(|dep| dep.name_in_toml().to_string()),
+    );
+    let target_cfg = CfgSet::for_target(config, target)?;
+    let host_cfg = match target {
+        Some(_) => CfgSet::for_target(config, None)?,
+        None => target_cfg.clone(),
+    };
+    let cfg = PackageCfg {
+        target: target_cfg.with_features(&features),
+        host: host_cfg.with_features(&features),
+    };
+    // the dependencies aren't there to go by
+    let (files, _) = package_files(&package, &cfg)?;
+    let targets = unsafe_code_of_package(&files, &HashSet::new());
+    Ok(Version {
+        version: version.to_string(),
+        root,
+        targets,
+    })
+}
+
+/// the unsafe sites of some code that ended up in one place, like the sites
+/// an item lost or gained
+#[derive(Default)]
+struct Region<'a> {
+    labels: BTreeSet<String>,
+    sites: Vec<&'a CodeLine>,
+}
+
+impl<'a> Region<'a> {
+    /// the lines from the first site through the last, 1-based
+    fn lines(&self) -> (usize, usize) {
+        let start = self.sites.iter().map(|code| code.line_number).min();
+        let end = self.sites.iter().map(|code| code.end_line_number).max();
+        (start.unwrap_or(0), end.unwrap_or(0))
+    }
+
+    fn text(&self) -> Vec<String> {
+        let (start, end) = self.lines();
+        match self.sites.first() {
+            Some(code) => fs::read_to_string(&code.file_path)
+                .unwrap_or_default()
+                .lines()
+                .skip(start - 1)
+                .take(end + 1 - start)
+                .map(String::from)
+                .collect(),
+            None => vec![],
+        }
+    }
+}
+
+/// the sites of a version over the targets that get built, or all of them
+fn sites_of(version: &Version, all_targets: bool) -> Vec<(String, &CodeLine)> {
+    unique_sites(built_targets(&version.targets, all_targets).map(|(_, code)| code))
+}
+
+/// the sites whose fingerprint the other side doesn't have as many of, by
+/// the item they're in and the file it's in relative to the crate's root
+fn unmatched<'a>(
+    sites: &[(String, &'a CodeLine)],
+    others: &[(String, &CodeLine)],
+    root: &Path,
+) -> BTreeMap<(String, PathBuf), Region<'a>> {
+    let mut other_counts: HashMap<&str, usize> = HashMap::new();
+    for (_, code) in others {
+        let hash = code.fingerprint.as_ref().unwrap().hash.as_str();
+        *other_counts.entry(hash).or_insert(0) += 1;
+    }
+    let mut regions: BTreeMap<(String, PathBuf), Region> = BTreeMap::new();
+    for (label, code) in sites {
+        let fingerprint = code.fingerprint.as_ref().unwrap();
+        match other_counts.get_mut(fingerprint.hash.as_str()) {
+            Some(count) if *count > 0 => *count -= 1,
+            _ => {
+                let file = code.file_path.strip_prefix(root).unwrap_or(&code.file_path);
+                let region = regions
+                    .entry((fingerprint.item.clone(), file.to_path_buf()))
+                    .or_default();
+                region.labels.insert(label.clone());
+                region.sites.push(code);
+            }
+        }
+    }
+    regions
+}
+
+/// a line diff by longest common subsequence, with each line marked ` `, `-`
+/// or `+`
+fn diff_lines(old: &[String], new: &[String]) -> Vec<(char, String)> {
+    let mut common = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut lines = vec![];
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push((' ', old[i].clone()));
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(('-', old[i].clone()));
+            i += 1;
+        } else {
+            lines.push(('+', new[j].clone()));
+            j += 1;
+        }
+    }
+    lines
+}
+
+/// e.g. `76,3`, or `0,0` when there's nothing on that side
+fn range(region: Option<&Region>) -> String {
+    match region {
+        Some(region) => {
+            let (start, end) = region.lines();
+            format!("{},{}", start, end + 1 - start)
+        }
+        None => "0,0".to_string(),
+    }
+}
+
+/// prints the unsafe code of `name` that was added, removed or modified going
+/// from `old` to `new`, as a unified diff of just the regions around it.
+/// sites are matched up by fingerprint, and what's left over is grouped by
+/// the item it's in: an item with sites left over on both sides was modified.
+/// the summary counts sites, so `unchanged`, `modified` and `added` add up to
+/// the sites of `new`
+pub fn print_crate_diff(name: &str, old: &Version, new: &Version, all_targets: bool) {
+    let old_sites = sites_of(old, all_targets);
+    let new_sites = sites_of(new, all_targets);
+    let mut removed = unmatched(&old_sites, &new_sites, &old.root);
+    let mut added = unmatched(&new_sites, &old_sites, &new.root);
+    let unchanged = old_sites.len() - removed.values().map(|r| r.sites.len()).sum::<usize>();
+    let keys: BTreeSet<(String, PathBuf)> = removed.keys().chain(added.keys()).cloned().collect();
+
+    println!("{} v{} -> v{}", name, old.version, new.version);
+    let mut counts = BTreeMap::new();
+    for key in keys {
+        let (item, file) = &key;
+        let old_region = removed.remove(&key);
+        let new_region = added.remove(&key);
+        let change = match (&old_region, &new_region) {
+            (Some(_), Some(_)) => "modified",
+            (Some(_), None) => "removed",
+            _ => "added",
+        };
+        // sites, like `unchanged`, rather than items. a modified item counts
+        // the sites it has now
+        let region = new_region.as_ref().or(old_region.as_ref());
+        *counts.entry(change).or_insert(0) += region.map_or(0, |r| r.sites.len());
+        let labels: BTreeSet<&String> = old_region
+            .iter()
+            .chain(&new_region)
+            .flat_map(|region| &region.labels)
+            .collect();
+        println!();
+        println!(
+            "{} {}: {}",
+            change,
+            if item.is_empty() { "crate root" } else { item },
+            labels.into_iter().cloned().collect::<Vec<_>>().join(", ")
+        );
+        let path = |version: &Version, region: &Option<Region>| match region {
+            Some(_) => format!("{}-{}/{}", name, version.version, file.display()),
+            None => "/dev/null".to_string(),
+        };
+        println!("--- {}", path(old, &old_region));
+        println!("+++ {}", path(new, &new_region));
+        println!(
+            "@@ -{} +{} @@ {}",
+            range(old_region.as_ref()),
+            range(new_region.as_ref()),
+            item
+        );
+        let old_text = old_region.as_ref().map(Region::text).unwrap_or_default();
+        let new_text = new_region.as_ref().map(Region::text).unwrap_or_default();
+        for (mark, line) in diff_lines(&old_text, &new_text) {
+            println!("{}{}", mark, line);
+        }
+    }
+    println!();
+    println!(
+        "unsafe sites: {} unchanged, {} modified, {} added, {} removed",
+        unchanged,
+        counts.get("modified").unwrap_or(&0),
+        counts.get("added").unwrap_or(&0),
+        counts.get("removed").unwrap_or(&0)
+    );
+}
@@ -0,0 +1,360 @@
+use cargo::core::dependency::Kind;
+use cargo::core::package_id::PackageId;
+use cargo::core::registry::PackageRegistry;
+use cargo::core::resolver::{EncodableResolve, Method};
+use cargo::core::{Package, PackageIdSpec, PackageSet, Resolve, Workspace};
+use cargo::ops;
+use cargo::util::CargoResult;
+use petgraph::graph::{Graph, NodeIndex};
+use petgraph::visit::{Dfs, EdgeRef};
+
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::fs;
+
+/// how one package depends on another
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DepKind {
+    Normal,
+    /// `[build-dependencies]`, which get built and run on the machine doing
+    /// the build, as part of running a build script
+    Build,
+    /// `[dev-dependencies]`, only built for tests, examples and benches
+    Dev,
+}
+
+impl DepKind {
+    pub fn of(kind: Kind) -> DepKind {
+        match kind {
+            Kind::Normal => DepKind::Normal,
+            Kind::Build => DepKind::Build,
+            Kind::Development => DepKind::Dev,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DepKind::Normal => "normal",
+            DepKind::Build => "build",
+            DepKind::Dev => "dev",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<DepKind> {
+        match name {
+            "normal" => Some(DepKind::Normal),
+            "build" => Some(DepKind::Build),
+            "dev" => Some(DepKind::Dev),
+            _ => None,
+        }
+    }
+}
+
+/// e.g. ` [depth 2, normal, build]`, or nothing for a root package. with
+/// `members` it also tells which of them use the package, like
+/// ` [depth 2, normal; used by app, cli]`
+pub fn dependency_tag(depth: usize, kinds: &BTreeSet<DepKind>, members: &[String]) -> String {
+    if depth == 0 {
+        return String::new();
+    }
+    let mut tags = vec![format!("depth {}", depth)];
+    tags.extend(kinds.iter().map(|kind| kind.name().to_string()));
+    let mut tags = tags.join(", ");
+    if !members.is_empty() {
+        tags = format!("{}; used by {}", tags, members.join(", "));
+    }
+    format!(" [{}]", tags)
+}
+
+/// the packages reached from the roots and the dependency edges between them.
+/// two packages can have two edges between them, e.g. when one is both a
+/// normal and a build dependency of the other
+pub struct DepGraph {
+    pub graph: Graph<Package, DepKind>,
+    /// the workspace members we started from
+    pub roots: Vec<NodeIndex>,
+}
+
+impl DepGraph {
+    /// `node` and every package it depends on, directly or not
+    pub fn subtree(&self, node: NodeIndex) -> Vec<NodeIndex> {
+        let mut nodes = vec![];
+        let mut dfs = Dfs::new(&self.graph, node);
+        while let Some(node) = dfs.next(&self.graph) {
+            nodes.push(node);
+        }
+        nodes
+    }
+
+    /// the unsafe lines of `node` and everything under it, out of each
+    /// package's `own`. a package depended on twice in the subtree only counts
+    /// once
+    pub fn subtree_total(&self, node: NodeIndex, own: &HashMap<NodeIndex, usize>) -> usize {
+        self.subtree(node)
+            .iter()
+            .map(|node| own.get(node).cloned().unwrap_or(0))
+            .sum()
+    }
+
+    /// the kinds of dependency `parent` has on `child`
+    pub fn edge_kinds(&self, parent: NodeIndex, child: NodeIndex) -> BTreeSet<DepKind> {
+        self.graph
+            .edges(parent)
+            .filter(|edge| edge.target() == child)
+            .map(|edge| *edge.weight())
+            .collect()
+    }
+}
+
+/// e.g. ` [normal, build]` for a dependency that's more than a normal one, or
+/// nothing for one that isn't
+pub fn edge_tag(kinds: &BTreeSet<DepKind>) -> String {
+    if kinds.iter().all(|kind| *kind == DepKind::Normal) {
+        return String::new();
+    }
+    let names: Vec<&str> = kinds.iter().map(|kind| kind.name()).collect();
+    format!(" [{}]", names.join(", "))
+}
+
+/// a package in the dependency graph along with the features it ended up with
+pub struct Resolved {
+    pub package: Package,
+    pub node: NodeIndex,
+    pub features: Vec<String>,
+    /// the kinds of dependency edge it was reached through. anything a build
+    /// dependency pulls in counts as build, and so on for dev. empty for a
+    /// root that no other root depends on
+    pub dep_kinds: BTreeSet<DepKind>,
+    /// the length of the shortest path to it from a root
+    pub depth: usize,
+    /// the names of the roots that depend on it, or are it
+    pub members: Vec<String>,
+}
+
+/// which parts of the dependency graph to resolve and follow
+#[derive(Clone)]
+pub struct ResolveOptions {
+    /// normal dependencies are always followed, other kinds only when they're
+    /// in here
+    pub dep_kinds: BTreeSet<DepKind>,
+    /// how many edges away from the roots to go, if not all the way
+    pub max_depth: Option<usize>,
+    /// features of the root packages, as given to `--features`
+    pub features: Vec<String>,
+    pub all_features: bool,
+    pub no_default_features: bool,
+    /// the contents of a Cargo.lock to take the versions from, instead of the
+    /// workspace's own
+    pub lockfile: Option<String>,
+    /// without a `lockfile`, hold the workspace's own Cargo.lock to the same:
+    /// read it but don't update it, and fail when the manifests don't fit it
+    pub locked: bool,
+}
+
+/// like `ops::resolve_ws_precisely`, but sticking to the versions locked in
+/// `lockfile`. the workspace's own Cargo.lock is left alone. it's an error
+/// when the manifests don't fit the lockfile, since resolving them would
+/// quietly pick other versions than the ones locked
+fn resolve_with_lockfile<'a>(
+    workspace: &Workspace<'a>,
+    lockfile: &str,
+    options: &ResolveOptions,
+    specs: &[PackageIdSpec],
+) -> CargoResult<(PackageSet<'a>, Resolve)> {
+    let encoded: EncodableResolve = toml::from_str(lockfile)?;
+    let previous = encoded.into_resolve(workspace)?;
+    let mut registry = PackageRegistry::new(workspace.config())?;
+    // the whole workspace first, like cargo does, and then only what was
+    // asked for
+    let resolve = ops::resolve_with_previous(
+        &mut registry,
+        workspace,
+        Method::Everything,
+        Some(&previous),
+        None,
+        &[],
+        true,
+        false,
+    )?;
+    let locked: BTreeSet<&PackageId> = previous.iter().collect();
+    let resolved: BTreeSet<&PackageId> = resolve.iter().collect();
+    if locked != resolved {
+        let mut changes = vec![];
+        for (change, ids) in &[
+            ("adds", resolved.difference(&locked)),
+            ("drops", locked.difference(&resolved)),
+        ] {
+            let ids: Vec<String> = ids.clone().map(|id| id.to_string()).collect();
+            if !ids.is_empty() {
+                changes.push(format!("{} {}", change, ids.join(", ")));
+            }
+        }
+        failure::bail!(
+            "the lockfile doesn't fit the manifests, resolving them with it {}",
+            changes.join(" and ")
+        );
+    }
+    ops::add_overrides(&mut registry, workspace)?;
+    let features = Method::split_features(&options.features);
+    let method = if options.all_features {
+        Method::Everything
+    } else {
+        Method::Required {
+            dev_deps: true,
+            features: &features,
+            all_features: false,
+            uses_default_features: !options.no_default_features,
+        }
+    };
+    let resolve = ops::resolve_with_previous(
+        &mut registry,
+        workspace,
+        method,
+        Some(&resolve),
+        None,
+        specs,
+        false,
+        false,
+    )?;
+    let packages = ops::get_resolved_packages(&resolve, registry)?;
+    Ok((packages, resolve))
+}
+
+/// every package the roots depend on, resolved with the features in `options`
+/// the way cargo would for a build, and followed as far as `options` says.
+/// every package shows up once, however many roots use it. the packages are
+/// ordered by depth
+pub fn resolve_packages(
+    workspace: &Workspace,
+    roots: &[Package],
+    options: &ResolveOptions,
+) -> CargoResult<(DepGraph, Vec<Resolved>)> {
+    let specs: Vec<PackageIdSpec> = roots
+        .iter()
+        .map(|root| PackageIdSpec::from_package_id(root.package_id()))
+        .collect();
+    let own_lockfile = if options.locked && options.lockfile.is_none() {
+        let path = workspace.root().join("Cargo.lock");
+        match fs::read_to_string(&path) {
+            Ok(lockfile) => Some(lockfile),
+            Err(e) => failure::bail!("couldn't read {}: {}", path.display(), e),
+        }
+    } else {
+        None
+    };
+    let (packages, resolve) = match options.lockfile.as_ref().or(own_lockfile.as_ref()) {
+        Some(lockfile) => resolve_with_lockfile(workspace, lockfile, options, &specs)?,
+        None => ops::resolve_ws_precisely(
+            workspace,
+            None,
+            &options.features,
+            options.all_features,
+            options.no_default_features,
+            &specs,
+        )?,
+    };
+    let dep_kinds = &options.dep_kinds;
+
+    let mut graph = Graph::new();
+    let mut nodes: HashMap<&PackageId, NodeIndex> = HashMap::new();
+    let mut expanded: HashSet<&PackageId> = HashSet::new();
+    // breadth first, so the first time a package is reached is by its
+    // shortest path
+    let mut reached: HashMap<&PackageId, (usize, BTreeSet<DepKind>)> = HashMap::new();
+    let mut root_nodes = vec![];
+    let mut to_check = VecDeque::new();
+    for root in roots {
+        let id = root.package_id();
+        let node = graph.add_node(root.clone());
+        root_nodes.push(node);
+        nodes.insert(id, node);
+        reached.insert(id, (0, BTreeSet::new()));
+        to_check.push_back((id, 0, BTreeSet::new()));
+    }
+    while let Some((id, depth, kinds)) = to_check.pop_front() {
+        if options
+            .max_depth
+            .is_some_and(|max_depth| depth >= max_depth)
+        {
+            continue;
+        }
+        let first_visit = expanded.insert(id);
+        if let Ok(package) = packages.get_one(id) {
+            let deps = resolve.deps_not_replaced(id);
+            for dep_id in deps {
+                let edges: Vec<DepKind> = package
+                    .dependencies()
+                    .iter()
+                    .filter(|d| d.matches_id(dep_id))
+                    .map(|d| DepKind::of(d.kind()))
+                    .filter(|kind| *kind == DepKind::Normal || dep_kinds.contains(kind))
+                    .collect();
+                if edges.is_empty() {
+                    if !package.dependencies().iter().any(|d| d.matches_id(dep_id)) {
+                        panic!("Looking up a packages dependency in the package failed, failed to find '{}' in '{}'", dep_id, id);
+                    }
+                    continue;
+                }
+                let dep_id = resolve.replacement(dep_id).unwrap_or(dep_id);
+                let dep_package = packages.get_one(dep_id)?;
+                let dep_node = *nodes
+                    .entry(dep_id)
+                    .or_insert_with(|| graph.add_node(dep_package.clone()));
+                let mut dep_kinds_reached = BTreeSet::new();
+                for edge in edges {
+                    if first_visit {
+                        graph.add_edge(nodes[id], dep_node, edge);
+                    }
+                    if edge == DepKind::Normal && !kinds.is_empty() {
+                        dep_kinds_reached.extend(kinds.iter().cloned());
+                    } else {
+                        dep_kinds_reached.insert(edge);
+                    }
+                }
+                let (_, seen) = reached
+                    .entry(dep_id)
+                    .or_insert_with(|| (depth + 1, BTreeSet::new()));
+                let new: BTreeSet<DepKind> = dep_kinds_reached.difference(seen).cloned().collect();
+                if !new.is_empty() {
+                    seen.extend(new.iter().cloned());
+                    to_check.push_back((dep_id, depth + 1, new));
+                }
+            }
+        }
+    }
+
+    let deps = DepGraph {
+        graph,
+        roots: root_nodes,
+    };
+    let mut members: HashMap<NodeIndex, Vec<String>> = HashMap::new();
+    for root in &deps.roots {
+        for node in deps.subtree(*root) {
+            members
+                .entry(node)
+                .or_default()
+                .push(deps.graph[*root].name().to_string());
+        }
+    }
+
+    let mut result = vec![];
+    for (id, (depth, kinds)) in reached {
+        let node = nodes[id];
+        let mut members = members.remove(&node).unwrap_or_default();
+        members.sort();
+        result.push(Resolved {
+            package: deps.graph[node].clone(),
+            node,
+            features: resolve
+                .features_sorted(id)
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            dep_kinds: kinds,
+            depth,
+            members,
+        });
+    }
+    result
+        .sort_by(|a, b| (a.depth, a.package.package_id()).cmp(&(b.depth, b.package.package_id())));
+    Ok((deps, result))
+}
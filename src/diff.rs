@@ -0,0 +1,288 @@
+use cargo::util::important_paths::find_root_manifest_for_wd;
+use cargo::Config;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::deps::ResolveOptions;
+use crate::{print_files, read_lockfile, MemberSelection, Result, UnsafeLines};
+
+/// e.g. `+3` or `-1`
+fn signed(was: usize, is: usize) -> String {
+    if is >= was {
+        format!("+{}", is - was)
+    } else {
+        format!("-{}", was - is)
+    }
+}
+
+/// e.g. `unsafe_lines 3, deref 2`, leaving out what there's none of
+fn counts_of(p: &UnsafeLines, all_targets: bool) -> String {
+    let counts: Vec<String> = p
+        .counts(all_targets)
+        .iter()
+        .filter(|(_, count)| **count > 0)
+        .map(|(name, count)| format!("{} {}", name, count))
+        .collect();
+    if counts.is_empty() {
+        "no unsafe code".to_string()
+    } else {
+        counts.join(", ")
+    }
+}
+
+/// e.g. `unsafe_lines 1 -> 2 (+1)`, for every count that changed
+fn changes(old: &UnsafeLines, new: &UnsafeLines, all_targets: bool) -> String {
+    let old = old.counts(all_targets);
+    let new = new.counts(all_targets);
+    let names: BTreeSet<&&str> = old.keys().chain(new.keys()).collect();
+    let changes: Vec<String> = names
+        .into_iter()
+        .filter_map(|name| {
+            let was = old.get(name).cloned().unwrap_or(0);
+            let is = new.get(name).cloned().unwrap_or(0);
+            if was == is {
+                None
+            } else {
+                Some(format!("{} {} -> {} ({})", name, was, is, signed(was, is)))
+            }
+        })
+        .collect();
+    if changes.is_empty() {
+        "no change in unsafe code".to_string()
+    } else {
+        changes.join(", ")
+    }
+}
+
+fn by_name(packages: &[UnsafeLines]) -> BTreeMap<String, Vec<&UnsafeLines>> {
+    let mut names: BTreeMap<String, Vec<&UnsafeLines>> = BTreeMap::new();
+    for p in packages {
+        names
+            .entry(p.package.name().to_string())
+            .or_default()
+            .push(p);
+    }
+    for versions in names.values_mut() {
+        versions.sort_by(|a, b| a.package.version().cmp(b.package.version()));
+    }
+    names
+}
+
+/// prints the crates that were added, removed, upgraded or downgraded going
+/// from `old` to `new`, with how their unsafe code changed. a crate that
+/// changed version is paired up with its old version, lowest to lowest when
+/// there are several
+pub fn print_diff(old: &[UnsafeLines], new: &[UnsafeLines], all_targets: bool) {
+    let old_names = by_name(old);
+    let new_names = by_name(new);
+    let names: BTreeSet<&String> = old_names.keys().chain(new_names.keys()).collect();
+    let mut changed = false;
+    for name in names {
+        let empty = vec![];
+        let old_versions = old_names.get(name).unwrap_or(&empty);
+        let new_versions = new_names.get(name).unwrap_or(&empty);
+        // a version on both sides didn't change, whatever else did
+        let same = |p: &&UnsafeLines, others: &[&UnsafeLines]| {
+            others
+                .iter()
+                .any(|other| other.package.version() == p.package.version())
+        };
+        let removed: Vec<&UnsafeLines> = old_versions
+            .iter()
+            .filter(|p| !same(p, new_versions))
+            .cloned()
+            .collect();
+        let added: Vec<&UnsafeLines> = new_versions
+            .iter()
+            .filter(|p| !same(p, old_versions))
+            .cloned()
+            .collect();
+        for (old, new) in removed.iter().zip(&added) {
+            changed = true;
+            println!(
+                "{} {} v{} -> v{}: {}",
+                if new.package.version() > old.package.version() {
+                    "upgraded"
+                } else {
+                    "downgraded"
+                },
+                name,
+                old.package.version(),
+                new.package.version(),
+                changes(old, new, all_targets)
+            );
+        }
+        for p in removed.iter().skip(added.len()) {
+            changed = true;
+            println!(
+                "removed {} v{}: {}",
+                name,
+                p.package.version(),
+                counts_of(p, all_targets)
+            );
+        }
+        for p in added.iter().skip(removed.len()) {
+            changed = true;
+            println!(
+                "added {} v{}: {}",
+                name,
+                p.package.version(),
+                counts_of(p, all_targets)
+            );
+        }
+    }
+    if !changed {
+        println!("no crates were added, removed, upgraded or downgraded");
+    }
+    let was: usize = old.iter().map(|p| p.line_count(all_targets)).sum();
+    let is: usize = new.iter().map(|p| p.line_count(all_targets)).sum();
+    println!(
+        "unsafe lines in total: {} -> {} ({})",
+        was,
+        is,
+        signed(was, is)
+    );
+}
+
+/// where the old side of a diff has its versions locked
+pub enum Old<'a> {
+    /// a Cargo.lock that goes with the workspace's manifests as they are
+    Lockfile(PathBuf),
+    /// the Cargo.lock of a git revision, along with its manifests
+    Rev(&'a str),
+}
+
+/// the unsafe code of the workspace's packages and their dependencies as
+/// `old` locks them
+pub fn old_packages(
+    config: &mut Config,
+    root: &Path,
+    manifest_path: Option<&Path>,
+    old: Old,
+    target: Option<&str>,
+    selection: &MemberSelection,
+    options: &ResolveOptions,
+) -> Result<Vec<UnsafeLines>> {
+    match old {
+        Old::Lockfile(lockfile) => {
+            let old_options = ResolveOptions {
+                lockfile: Some(read_lockfile(&lockfile)?),
+                ..options.clone()
+            };
+            let (_, _, old) = print_files(
+                config,
+                manifest_path,
+                target,
+                selection,
+                &old_options,
+                false,
+            )?;
+            Ok(old)
+        }
+        Old::Rev(rev) => {
+            let manifest_path = match manifest_path {
+                Some(manifest_path) => manifest_path.to_path_buf(),
+                None => find_root_manifest_for_wd(config.cwd())?,
+            };
+            files_at_rev(
+                config,
+                root,
+                &manifest_path,
+                rev,
+                target,
+                selection,
+                options,
+            )
+        }
+    }
+}
+
+/// the unsafe code of the workspace's packages and their dependencies at git
+/// revision `rev`, as locked by its Cargo.lock. the revision gets unpacked
+/// into a temporary directory for it
+fn files_at_rev(
+    config: &mut Config,
+    root: &Path,
+    manifest_path: &Path,
+    rev: &str,
+    target: Option<&str>,
+    selection: &MemberSelection,
+    options: &ResolveOptions,
+) -> Result<Vec<UnsafeLines>> {
+    let checkout = std::env::temp_dir().join(format!("cargo-danger-{}", std::process::id()));
+    let old = (|| {
+        let old_root = checkout_rev(root, rev, &checkout)?;
+        let lockfile = old_root.join("Cargo.lock");
+        if !lockfile.is_file() {
+            return Err(format!("there's no Cargo.lock at {}", rev).into());
+        }
+        let manifest_path = old_root.join(
+            manifest_path
+                .strip_prefix(root)
+                .unwrap_or_else(|_| Path::new("Cargo.toml")),
+        );
+        if !manifest_path.is_file() {
+            return Err(format!("there's no {} at {}", manifest_path.display(), rev).into());
+        }
+        let old_options = ResolveOptions {
+            lockfile: Some(read_lockfile(&lockfile)?),
+            ..options.clone()
+        };
+        print_files(
+            config,
+            Some(&manifest_path),
+            target,
+            selection,
+            &old_options,
+            false,
+        )
+    })();
+    let _ = fs::remove_dir_all(&checkout);
+    Ok(old?.2)
+}
+
+/// runs git in `dir`, for its output
+fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git").args(args).current_dir(dir).output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8(output.stdout)?.trim().to_string())
+}
+
+/// unpacks the git repository `root` is in as it was at `rev` into `into`,
+/// and returns where `root` ends up in there, so the old manifests and
+/// Cargo.lock can be read together
+fn checkout_rev(root: &Path, rev: &str, into: &Path) -> Result<PathBuf> {
+    let top_level = PathBuf::from(git(root, &["rev-parse", "--show-toplevel"])?);
+    let commit = git(
+        root,
+        &["rev-parse", "--verify", &format!("{}^{{commit}}", rev)],
+    )?;
+    fs::create_dir_all(into)?;
+    let mut archive = Command::new("git")
+        .args(["archive", "--format=tar", &commit])
+        .current_dir(&top_level)
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let unpacked = Command::new("tar")
+        .arg("-x")
+        .arg("-C")
+        .arg(into)
+        .stdin(archive.stdout.take().unwrap())
+        .status()?;
+    if !archive.wait()?.success() || !unpacked.success() {
+        return Err(format!("couldn't unpack {} into {}", rev, into.display()).into());
+    }
+    let root = root.canonicalize()?;
+    let top_level = top_level.canonicalize()?;
+    Ok(into.join(root.strip_prefix(&top_level)?))
+}
@@ -0,0 +1,243 @@
+use proc_macro2::TokenStream;
+use quote::ToTokens;
+use syn::visit::{self, Visit};
+use syn::{
+    Abi, Attribute, FnDecl, ForeignItemFn, ForeignItemStatic, ImplItemMethod, ItemFn,
+    ItemForeignMod, ItemStatic, Lit, Meta, NestedMeta,
+};
+
+use crate::walker::Location;
+
+/// the different ways a crate can reach across the FFI boundary
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FfiKind {
+    /// `#[link(name = "...")]` on an extern block
+    Link,
+    /// a function declared in an `extern { ... }` block
+    ForeignFn,
+    /// a static declared in an `extern { ... }` block
+    ForeignStatic,
+    /// an `extern "C" fn` defined in rust, callable from the other side
+    ExternFn,
+    /// an item exported under a fixed symbol name with `#[no_mangle]` or
+    /// `#[export_name = "..."]`
+    Export,
+}
+
+impl FfiKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            FfiKind::Link => "link",
+            FfiKind::ForeignFn => "foreign fn",
+            FfiKind::ForeignStatic => "foreign static",
+            FfiKind::ExternFn => "extern fn",
+            FfiKind::Export => "export",
+        }
+    }
+}
+
+/// one entry of a file's FFI surface
+#[derive(Debug)]
+pub struct FfiSite {
+    pub kind: FfiKind,
+    /// `None` for things the ABI doesn't apply to, like `#[link]`
+    pub abi: Option<String>,
+    /// the library linked to, the symbol exported, or the function declared
+    pub name: String,
+    /// covers the signature of the item, without its attributes or body
+    pub location: Location,
+}
+
+struct FfiVisitor {
+    /// the ABI of the extern block we're in, if we're in one
+    foreign_abi: Option<String>,
+    sites: Vec<FfiSite>,
+}
+
+/// `extern { ... }` and `extern fn` without an explicit ABI mean "C"
+fn abi_name(abi: &Abi) -> String {
+    abi.name
+        .as_ref()
+        .map(|name| name.value())
+        .unwrap_or_else(|| "C".to_string())
+}
+
+/// the symbol an item is exported as, if it's exported under a fixed one
+fn exported_symbol(attrs: &[Attribute], ident: &syn::Ident) -> Option<String> {
+    for attr in attrs {
+        match attr.interpret_meta() {
+            Some(Meta::Word(ref word)) if word == "no_mangle" => return Some(ident.to_string()),
+            Some(Meta::NameValue(ref name_value)) if name_value.ident == "export_name" => {
+                if let Lit::Str(symbol) = &name_value.lit {
+                    return Some(symbol.value());
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// the `name = "..."` of a `#[link(...)]` attribute
+fn linked_library(attr: &Attribute) -> Option<String> {
+    let list = match attr.interpret_meta() {
+        Some(Meta::List(list)) => list,
+        _ => return None,
+    };
+    if list.ident != "link" {
+        return None;
+    }
+    for nested in &list.nested {
+        if let NestedMeta::Meta(Meta::NameValue(name_value)) = nested {
+            if name_value.ident == "name" {
+                if let Lit::Str(name) = &name_value.lit {
+                    return Some(name.value());
+                }
+            }
+        }
+    }
+    None
+}
+
+/// a function's signature up to but not including its body, starting from
+/// whichever of `prefix` comes first
+fn signature_location(prefix: &[&dyn ToTokens], decl: &FnDecl) -> Option<Location> {
+    let mut tokens = TokenStream::new();
+    for part in prefix {
+        part.to_tokens(&mut tokens);
+    }
+    decl.fn_token.to_tokens(&mut tokens);
+    decl.paren_token.surround(&mut tokens, |_| {});
+    decl.output.to_tokens(&mut tokens);
+    Location::of(&tokens)
+}
+
+impl FfiVisitor {
+    fn push(
+        &mut self,
+        kind: FfiKind,
+        abi: Option<String>,
+        name: String,
+        location: Option<Location>,
+    ) {
+        if let Some(location) = location {
+            self.sites.push(FfiSite {
+                kind,
+                abi,
+                name,
+                location,
+            });
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for FfiVisitor {
+    fn visit_item_foreign_mod(&mut self, foreign_mod: &'ast ItemForeignMod) {
+        for attr in &foreign_mod.attrs {
+            if let Some(library) = linked_library(attr) {
+                self.push(FfiKind::Link, None, library, Location::of(attr));
+            }
+        }
+        self.foreign_abi = Some(abi_name(&foreign_mod.abi));
+        visit::visit_item_foreign_mod(self, foreign_mod);
+        self.foreign_abi = None;
+    }
+
+    fn visit_foreign_item_fn(&mut self, foreign_fn: &'ast ForeignItemFn) {
+        let mut signature = foreign_fn.clone();
+        signature.attrs.clear();
+        self.push(
+            FfiKind::ForeignFn,
+            self.foreign_abi.clone(),
+            foreign_fn.ident.to_string(),
+            Location::of(&signature),
+        );
+    }
+
+    fn visit_foreign_item_static(&mut self, foreign_static: &'ast ForeignItemStatic) {
+        let mut signature = foreign_static.clone();
+        signature.attrs.clear();
+        self.push(
+            FfiKind::ForeignStatic,
+            self.foreign_abi.clone(),
+            foreign_static.ident.to_string(),
+            Location::of(&signature),
+        );
+    }
+
+    fn visit_item_fn(&mut self, fn_def: &'ast ItemFn) {
+        let location = signature_location(
+            &[
+                &fn_def.vis,
+                &fn_def.constness,
+                &fn_def.unsafety,
+                &fn_def.asyncness,
+                &fn_def.abi,
+            ],
+            &fn_def.decl,
+        );
+        let abi = fn_def.abi.as_ref().map(abi_name);
+        if let Some(abi) = &abi {
+            self.push(
+                FfiKind::ExternFn,
+                Some(abi.clone()),
+                fn_def.ident.to_string(),
+                location,
+            );
+        }
+        if let Some(symbol) = exported_symbol(&fn_def.attrs, &fn_def.ident) {
+            self.push(FfiKind::Export, abi, symbol, location);
+        }
+        visit::visit_item_fn(self, fn_def);
+    }
+
+    fn visit_impl_item_method(&mut self, method_impl: &'ast ImplItemMethod) {
+        let sig = &method_impl.sig;
+        let location = signature_location(
+            &[
+                &method_impl.vis,
+                &method_impl.defaultness,
+                &sig.constness,
+                &sig.unsafety,
+                &sig.asyncness,
+                &sig.abi,
+            ],
+            &sig.decl,
+        );
+        let abi = sig.abi.as_ref().map(abi_name);
+        if let Some(abi) = &abi {
+            self.push(
+                FfiKind::ExternFn,
+                Some(abi.clone()),
+                sig.ident.to_string(),
+                location,
+            );
+        }
+        if let Some(symbol) = exported_symbol(&method_impl.attrs, &sig.ident) {
+            self.push(FfiKind::Export, abi, symbol, location);
+        }
+        visit::visit_impl_item_method(self, method_impl);
+    }
+
+    fn visit_item_static(&mut self, static_def: &'ast ItemStatic) {
+        if let Some(symbol) = exported_symbol(&static_def.attrs, &static_def.ident) {
+            let mut tokens = TokenStream::new();
+            static_def.vis.to_tokens(&mut tokens);
+            static_def.static_token.to_tokens(&mut tokens);
+            static_def.ty.to_tokens(&mut tokens);
+            let location = Location::of(&tokens);
+            self.push(FfiKind::Export, None, symbol, location);
+        }
+        visit::visit_item_static(self, static_def);
+    }
+}
+
+/// everything in a file that crosses the FFI boundary, in either direction
+pub fn ffi_sites_of_file(file: &syn::File) -> Vec<FfiSite> {
+    let mut visitor = FfiVisitor {
+        foreign_abi: None,
+        sites: vec![],
+    };
+    visitor.visit_file(file);
+    visitor.sites
+}
@@ -0,0 +1,317 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::code_line::{CodeLine, UnsafeCode};
+use crate::walker::Category;
+use crate::UnsafeLines;
+
+/// everything the page needs in one go, so it still works as an attachment
+const STYLE: &str = r#"
+body { font-family: sans-serif; margin: 2em; color: #222; }
+table.packages { border-collapse: collapse; }
+table.packages th, table.packages td { padding: 0.3em 0.8em; border-bottom: 1px solid #ddd; text-align: left; }
+table.packages th { cursor: pointer; user-select: none; background: #f4f4f4; }
+table.packages td.n { text-align: right; }
+details { margin: 0.5em 0; }
+summary { cursor: pointer; }
+h2 { margin-top: 2em; }
+table.source { border-collapse: collapse; font-family: monospace; font-size: 0.9em; }
+table.source td { padding: 0 0.5em; white-space: pre; vertical-align: top; }
+table.source td.ln { color: #999; text-align: right; user-select: none; }
+table.source tr.unsafe { background: #fff6d5; }
+.legend span { display: inline-block; padding: 0 0.4em; margin-right: 0.5em; }
+.decl { background: #d9e7ff; }
+.deref { background: #ffb3b3; }
+.call { background: #ffd1a3; }
+.static_mut { background: #e6c3ff; }
+.union_field { background: #c3f0d0; }
+.asm { background: #ff8a8a; }
+.unsafe_impl { background: #b9e2ff; }
+"#;
+
+/// sorts the package table by the column whose header was clicked, numbers
+/// by value, clicking again reverses it
+const SCRIPT: &str = r#"
+document.querySelectorAll("table.packages th").forEach(function (th, column) {
+  th.addEventListener("click", function () {
+    var body = th.closest("table").tBodies[0];
+    var rows = Array.prototype.slice.call(body.rows);
+    var ascending = th.dataset.order !== "asc";
+    th.dataset.order = ascending ? "asc" : "desc";
+    rows.sort(function (a, b) {
+      var x = a.cells[column].dataset.sort, y = b.cells[column].dataset.sort;
+      var order = isNaN(x) || isNaN(y) ? x.localeCompare(y) : x - y;
+      return ascending ? order : -order;
+    });
+    rows.forEach(function (row) { body.appendChild(row); });
+  });
+});
+"#;
+
+/// one row of the package table and the files it drills down to
+struct Entry<'a> {
+    name: String,
+    version: String,
+    /// the kinds of dependency it was reached through, e.g. `normal, build`
+    kinds: String,
+    depth: usize,
+    /// file paths are shown relative to this
+    root: PathBuf,
+    code: Vec<&'a UnsafeCode>,
+}
+
+/// a span to highlight within a file, the more specific ones win where they
+/// overlap
+struct Highlight {
+    class: &'static str,
+    title: String,
+    priority: u8,
+    code: CodeLine,
+}
+
+/// every file with something unsafe in it, with what to highlight
+fn highlights_by_file(code: &[&UnsafeCode]) -> BTreeMap<PathBuf, Vec<Highlight>> {
+    let mut files: BTreeMap<PathBuf, Vec<Highlight>> = BTreeMap::new();
+    let mut add = |class, title: String, priority, code: &CodeLine| {
+        files
+            .entry(code.file_path.clone())
+            .or_default()
+            .push(Highlight {
+                class,
+                title,
+                priority,
+                code: code.clone(),
+            });
+    };
+    for code in code {
+        for line in &code.lines {
+            add("line", "unsafe line".to_string(), 0, line);
+        }
+        for declaration in &code.declarations {
+            let title = format!("unsafe {} declaration", declaration.kind.name());
+            add("decl", title, 1, &declaration.code);
+        }
+        for operation in &code.operations {
+            let name = operation.category.name();
+            add(name, name.to_string(), 2, &operation.code);
+        }
+    }
+    files
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// the source of `path` with line numbers, with the lines of unsafe code
+/// marked and the declarations and operations in them highlighted
+fn render_source(out: &mut String, path: &Path, highlights: &[Highlight]) {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            let _ = writeln!(
+                out,
+                "<p>couldn't read {}: {}</p>",
+                escape(&path.display().to_string()),
+                escape(&e.to_string())
+            );
+            return;
+        }
+    };
+    out.push_str("<table class=\"source\">\n");
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let chars: Vec<char> = line.chars().collect();
+        // which highlight each char falls in, 1-based like the columns
+        let mut marks: Vec<Option<&Highlight>> = vec![None; chars.len() + 1];
+        let mut unsafe_line = false;
+        for highlight in highlights {
+            let code = &highlight.code;
+            if line_number < code.line_number || line_number > code.end_line_number {
+                continue;
+            }
+            if highlight.priority == 0 {
+                unsafe_line = true;
+                continue;
+            }
+            let start = if line_number == code.line_number {
+                code.column
+            } else {
+                1
+            };
+            let end = if line_number == code.end_line_number {
+                code.end_column.min(chars.len() + 1)
+            } else {
+                chars.len() + 1
+            };
+            for mark in marks.iter_mut().take(end).skip(start) {
+                if mark.is_none_or(|m| m.priority <= highlight.priority) {
+                    *mark = Some(highlight);
+                }
+            }
+        }
+
+        let mut text = String::new();
+        let mut open: Option<&Highlight> = None;
+        for (column, c) in chars.iter().enumerate() {
+            let mark = marks[column + 1];
+            if !same(open, mark) {
+                if open.is_some() {
+                    text.push_str("</span>");
+                }
+                if let Some(mark) = mark {
+                    let _ = write!(
+                        text,
+                        "<span class=\"{}\" title=\"{}\">",
+                        mark.class,
+                        escape(&mark.title)
+                    );
+                }
+                open = mark;
+            }
+            text.push_str(&escape(&c.to_string()));
+        }
+        if open.is_some() {
+            text.push_str("</span>");
+        }
+        let _ = writeln!(
+            out,
+            "<tr{}><td class=\"ln\">{}</td><td>{}</td></tr>",
+            if unsafe_line { " class=\"unsafe\"" } else { "" },
+            line_number,
+            text
+        );
+    }
+    out.push_str("</table>\n");
+}
+
+fn same(a: Option<&Highlight>, b: Option<&Highlight>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => std::ptr::eq(a, b),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+fn render(title: &str, entries: &[Entry]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>",
+        escape(title),
+        STYLE
+    );
+    let _ = writeln!(out, "<h1>{}</h1>", escape(title));
+    let _ = writeln!(
+        out,
+        "<p>generated by {} v{}</p>",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    );
+    out.push_str("<p class=\"legend\"><span class=\"decl\">unsafe declaration</span>");
+    for category in &Category::ALL {
+        let _ = write!(out, "<span class=\"{0}\">{0}</span>", category.name());
+    }
+    out.push_str("</p>\n");
+
+    out.push_str("<table class=\"packages\">\n<thead><tr><th>package</th><th>version</th><th>dependency</th><th>depth</th><th>unsafe lines</th><th>unsafe declarations</th><th>unsafe operations</th></tr></thead>\n<tbody>\n");
+    for (i, entry) in entries.iter().enumerate() {
+        let lines: usize = entry.code.iter().map(|code| code.lines.len()).sum();
+        let declarations: usize = entry.code.iter().map(|code| code.declarations.len()).sum();
+        let operations: usize = entry.code.iter().map(|code| code.operations.len()).sum();
+        let _ = writeln!(
+            out,
+            "<tr><td data-sort=\"{0}\"><a href=\"#p{1}\">{0}</a></td><td data-sort=\"{2}\">{2}</td><td data-sort=\"{3}\">{3}</td><td class=\"n\" data-sort=\"{4}\">{4}</td><td class=\"n\" data-sort=\"{5}\">{5}</td><td class=\"n\" data-sort=\"{6}\">{6}</td><td class=\"n\" data-sort=\"{7}\">{7}</td></tr>",
+            escape(&entry.name),
+            i,
+            escape(&entry.version),
+            escape(&entry.kinds),
+            entry.depth,
+            lines,
+            declarations,
+            operations
+        );
+    }
+    out.push_str("</tbody>\n</table>\n");
+
+    for (i, entry) in entries.iter().enumerate() {
+        let _ = writeln!(
+            out,
+            "<h2 id=\"p{}\">{} {}</h2>",
+            i,
+            escape(&entry.name),
+            escape(&entry.version)
+        );
+        let files = highlights_by_file(&entry.code);
+        if files.is_empty() {
+            out.push_str("<p>no unsafe code</p>\n");
+        }
+        for (path, highlights) in files {
+            let shown = path.strip_prefix(&entry.root).unwrap_or(&path);
+            let lines: BTreeSet<usize> = highlights
+                .iter()
+                .filter(|h| h.priority == 0)
+                .map(|h| h.code.line_number)
+                .collect();
+            let _ = writeln!(
+                out,
+                "<details>\n<summary>{} ({} unsafe line{})</summary>",
+                escape(&shown.display().to_string()),
+                lines.len(),
+                if lines.len() == 1 { "" } else { "s" }
+            );
+            render_source(&mut out, &path, &highlights);
+            out.push_str("</details>\n");
+        }
+    }
+    let _ = write!(out, "<script>{}</script>\n</body>\n</html>", SCRIPT);
+    out
+}
+
+/// one row per package, with the targets that get built, or all of them
+pub fn render_packages(packages: &[UnsafeLines], all_targets: bool) -> String {
+    let entries: Vec<Entry> = packages
+        .iter()
+        .map(|p| {
+            let id = p.package.package_id();
+            Entry {
+                name: id.name().to_string(),
+                version: id.version().to_string(),
+                kinds: p
+                    .dep_kinds
+                    .iter()
+                    .map(|kind| kind.name())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                depth: p.depth,
+                root: p.package.root().to_path_buf(),
+                code: p.built_targets(all_targets).map(|(_, code)| code).collect(),
+            }
+        })
+        .collect();
+    render("unsafe code report", &entries)
+}
+
+pub fn render_directory(path: &Path, code: &UnsafeCode) -> String {
+    let entries = vec![Entry {
+        name: path.display().to_string(),
+        version: String::new(),
+        kinds: String::new(),
+        depth: 0,
+        root: path.to_path_buf(),
+        code: vec![code],
+    }];
+    render(&format!("unsafe code in {}", path.display()), &entries)
+}
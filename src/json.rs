@@ -0,0 +1,247 @@
+use serde_derive::Serialize;
+
+use std::collections::BTreeMap;
+use std::ops::AddAssign;
+use std::path::Path;
+
+use crate::code_line::{CodeLine, UnsafeCode};
+use crate::{Result, UnsafeLines};
+
+/// bumped whenever a field changes meaning or goes away. new fields don't
+/// bump it, so readers should ignore the ones they don't know
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct Report {
+    schema_version: u32,
+    tool: Tool,
+    summary: Summary,
+    packages: Vec<PackageReport>,
+    /// only there when a plain directory was searched instead of a project
+    #[serde(skip_serializing_if = "Option::is_none")]
+    directory: Option<DirectoryReport>,
+}
+
+#[derive(Serialize)]
+struct Tool {
+    name: &'static str,
+    version: &'static str,
+}
+
+#[derive(Serialize, Default)]
+struct Counts {
+    unsafe_lines: usize,
+    unsafe_declarations: usize,
+    /// operations per `Category::name`
+    categories: BTreeMap<&'static str, usize>,
+}
+
+impl Counts {
+    fn of(code: &UnsafeCode) -> Counts {
+        Counts {
+            unsafe_lines: code.lines.len(),
+            unsafe_declarations: code.declarations.len(),
+            categories: code
+                .category_counts()
+                .into_iter()
+                .map(|(category, count)| (category.name(), count))
+                .collect(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.unsafe_lines == 0 && self.unsafe_declarations == 0 && self.categories.is_empty()
+    }
+}
+
+impl AddAssign<&Counts> for Counts {
+    fn add_assign(&mut self, other: &Counts) {
+        self.unsafe_lines += other.unsafe_lines;
+        self.unsafe_declarations += other.unsafe_declarations;
+        for (category, count) in &other.categories {
+            *self.categories.entry(category).or_insert(0) += count;
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Summary {
+    packages: usize,
+    packages_with_unsafe: usize,
+    #[serde(flatten)]
+    counts: Counts,
+}
+
+#[derive(Serialize)]
+struct PackageReport {
+    name: String,
+    version: String,
+    /// like `registry+https://github.com/rust-lang/crates.io-index`
+    source: String,
+    /// the directory holding its Cargo.toml
+    root: String,
+    dep_kinds: Vec<&'static str>,
+    depth: usize,
+    members: Vec<String>,
+    #[serde(flatten)]
+    counts: Counts,
+    targets: Vec<TargetReport>,
+}
+
+#[derive(Serialize)]
+struct TargetReport {
+    kind: &'static str,
+    #[serde(flatten)]
+    counts: Counts,
+    sites: Vec<Site>,
+}
+
+#[derive(Serialize)]
+struct DirectoryReport {
+    path: String,
+    /// `path` relative to the directory cargo danger ran from when it's under
+    /// it, like SARIF's `%SRCROOT%`, so that a baseline saved in one checkout
+    /// still matches in another
+    relative_path: String,
+    #[serde(flatten)]
+    counts: Counts,
+    sites: Vec<Site>,
+}
+
+#[derive(Serialize)]
+struct Site {
+    /// `declaration`, `operation` or `line`
+    #[serde(rename = "type")]
+    site_type: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    declaration: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    category: Option<&'static str>,
+    file: String,
+    line: usize,
+    column: usize,
+    end_line: usize,
+    end_column: usize,
+    /// the code of the site on one line, see `CodeLine::snippet`
+    snippet: String,
+    /// the path of the item it's in and the hash that identifies it, see
+    /// `Fingerprint`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    item: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fingerprint: Option<String>,
+}
+
+impl Site {
+    fn new(site_type: &'static str, code: &CodeLine) -> Site {
+        Site {
+            site_type,
+            declaration: None,
+            category: None,
+            file: code.file_path.display().to_string(),
+            line: code.line_number,
+            column: code.column,
+            end_line: code.end_line_number,
+            end_column: code.end_column,
+            snippet: code.snippet(),
+            item: code.fingerprint.as_ref().map(|f| f.item.clone()),
+            fingerprint: code.fingerprint.as_ref().map(|f| f.hash.clone()),
+        }
+    }
+}
+
+fn sites_of(code: &UnsafeCode) -> Vec<Site> {
+    let mut sites = vec![];
+    for declaration in &code.declarations {
+        sites.push(Site {
+            declaration: Some(declaration.kind.name()),
+            ..Site::new("declaration", &declaration.code)
+        });
+    }
+    for operation in &code.operations {
+        sites.push(Site {
+            category: Some(operation.category.name()),
+            ..Site::new("operation", &operation.code)
+        });
+    }
+    for line in &code.lines {
+        sites.push(Site::new("line", line));
+    }
+    sites
+}
+
+fn report(packages: Vec<PackageReport>, directory: Option<DirectoryReport>) -> Report {
+    let mut summary = Summary {
+        packages: packages.len(),
+        packages_with_unsafe: 0,
+        counts: Counts::default(),
+    };
+    for package in &packages {
+        if !package.counts.is_empty() {
+            summary.packages_with_unsafe += 1;
+        }
+        summary.counts += &package.counts;
+    }
+    if let Some(directory) = &directory {
+        summary.counts += &directory.counts;
+    }
+    Report {
+        schema_version: SCHEMA_VERSION,
+        tool: Tool {
+            name: env!("CARGO_PKG_NAME"),
+            version: env!("CARGO_PKG_VERSION"),
+        },
+        summary,
+        packages,
+        directory,
+    }
+}
+
+/// one object per package, with the targets that get built, or all of them
+pub fn render_packages(packages: &[UnsafeLines], all_targets: bool) -> Result<String> {
+    let mut reports = vec![];
+    for p in packages {
+        let id = p.package.package_id();
+        let mut counts = Counts::default();
+        let mut targets = vec![];
+        for (kind, code) in p.built_targets(all_targets) {
+            let target = TargetReport {
+                kind: kind.name(),
+                counts: Counts::of(code),
+                sites: sites_of(code),
+            };
+            counts += &target.counts;
+            targets.push(target);
+        }
+        reports.push(PackageReport {
+            name: id.name().to_string(),
+            version: id.version().to_string(),
+            source: id.source_id().to_url().to_string(),
+            root: p.package.root().display().to_string(),
+            dep_kinds: p.dep_kinds.iter().map(|kind| kind.name()).collect(),
+            depth: p.depth,
+            members: p.members.clone(),
+            counts,
+            targets,
+        });
+    }
+    Ok(serde_json::to_string_pretty(&report(reports, None))?)
+}
+
+pub fn render_directory(cwd: &Path, path: &Path, code: &UnsafeCode) -> Result<String> {
+    let relative_path = match path.strip_prefix(cwd) {
+        Ok(relative) if relative.as_os_str().is_empty() => Path::new("."),
+        Ok(relative) => relative,
+        Err(_) => path,
+    };
+    let directory = DirectoryReport {
+        path: path.display().to_string(),
+        relative_path: relative_path.display().to_string(),
+        counts: Counts::of(code),
+        sites: sites_of(code),
+    };
+    Ok(serde_json::to_string_pretty(&report(
+        vec![],
+        Some(directory),
+    ))?)
+}
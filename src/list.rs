@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::code_line::CodeLine;
+
+/// prints the sites in the order they're in their files, one line each for a
+/// quickfix list or else as `print_sites` does
+pub fn print_list(mut sites: Vec<(String, &CodeLine)>, cwd: &Path, quickfix: bool, context: usize) {
+    sites.sort_by_key(|(_, code)| (code.file_path.clone(), code.line_number, code.column));
+    if quickfix {
+        print_quickfix(&sites, cwd);
+    } else {
+        print_sites(&sites, cwd, context);
+    }
+}
+
+/// prints sites like grep does: `path:line:col: category`, then the lines of
+/// the site marked with `:` and `context` lines either side marked with `-`.
+/// `--` goes between sites when there's context. paths under `cwd` are made
+/// relative to it
+fn print_sites(sites: &[(String, &CodeLine)], cwd: &Path, context: usize) {
+    let mut sources: HashMap<&PathBuf, Vec<String>> = HashMap::new();
+    for (i, (label, code)) in sites.iter().enumerate() {
+        if i > 0 && context > 0 {
+            println!("--");
+        }
+        println!("{}: {}", position(code, cwd), label);
+        let source = sources.entry(&code.file_path).or_insert_with(|| {
+            fs::read_to_string(&code.file_path)
+                .unwrap_or_default()
+                .lines()
+                .map(String::from)
+                .collect()
+        });
+        let first = code.line_number.saturating_sub(context).max(1);
+        let last = (code.end_line_number + context).min(source.len());
+        for line_number in first..=last {
+            let mark = if line_number < code.line_number || line_number > code.end_line_number {
+                '-'
+            } else {
+                ':'
+            };
+            println!("{}{}{}", line_number, mark, source[line_number - 1]);
+        }
+    }
+}
+
+/// prints one `file:line:col: message` line per site, for an editor's
+/// quickfix list
+fn print_quickfix(sites: &[(String, &CodeLine)], cwd: &Path) {
+    for (label, code) in sites {
+        println!("{}: {}: {}", position(code, cwd), label, code.snippet());
+    }
+}
+
+/// e.g. `src/lib.rs:76:29`
+fn position(code: &CodeLine, cwd: &Path) -> String {
+    format!(
+        "{}:{}:{}",
+        code.file_path
+            .strip_prefix(cwd)
+            .unwrap_or(&code.file_path)
+            .display(),
+        code.line_number,
+        code.column
+    )
+}
@@ -1,829 +1,961 @@
-use cargo::core::dependency::Kind;
-use cargo::core::package_id::PackageId;
+use cargo::core::manifest::TargetSourcePath;
 use cargo::core::{Package, Workspace};
-use cargo::ops;
 use cargo::util::CargoResult;
 use cargo::Config;
 
-use std::path::Path;
-use syn::Item;
+use std::path::{Path, PathBuf};
 
-use std::fs::File;
-use std::io::Read;
-
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
 use cargo::util::important_paths::find_root_manifest_for_wd;
 
-use clap::{App, Arg, SubCommand};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use petgraph::graph::NodeIndex;
 
-type Result<T> = std::result::Result<T, Box<std::error::Error>>;
+mod baseline;
+mod cfg;
+mod code_line;
+mod crate_diff;
+mod deps;
+mod diff;
+mod ffi;
+mod html;
+mod json;
+mod list;
+mod markdown;
+mod modules;
+mod policy;
+mod review;
+mod sarif;
+mod targets;
+mod text;
+mod tree;
+mod walker;
+mod why;
 
-/*
-struct CodeLine {
-    file_path: String,
-    line_number: usize,
-    raw_line: String,
-}
-*/
+use crate::baseline::Baseline;
+use crate::cfg::CfgSet;
+use crate::code_line::{unique_sites, UnsafeCode};
+use crate::crate_diff::cached_version;
+use crate::deps::{resolve_packages, DepGraph, DepKind, ResolveOptions};
+use crate::diff::Old;
+use crate::ffi::ffi_sites_of_file;
+use crate::modules::{crate_files, parse_source, SourceFile};
+use crate::policy::check_packages;
+use crate::review::{record_reviews, Ledger, LEDGER_FILE};
+use crate::targets::{built_targets, TargetKind};
+use crate::tree::print_tree;
+use crate::walker::{unsafe_sites_of_file, Declared};
+use crate::why::print_why;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
 #[derive(Debug)]
 struct UnsafeLines {
     package: Package,
-    //lines: Vec<CodeLine>
-    lines: usize,
+    /// the kinds of dependency edge the package was reached through
+    dep_kinds: BTreeSet<DepKind>,
+    /// how many edges away from a root it is, at the least
+    depth: usize,
+    /// the names of the workspace members that use it
+    members: Vec<String>,
+    /// kept apart per kind of target, since unsafe code in a build script or a
+    /// test is a different matter from unsafe code in a lib
+    targets: BTreeMap<TargetKind, UnsafeCode>,
+    /// .rs files in the package that none of its targets use
+    orphans: Vec<PathBuf>,
+    node: NodeIndex,
 }
 
-fn parse_input() -> Result<()> {
-    let matches = App::new("cargo-danger")
-        .version("1.0")
-        .about("Detect unsafe code")
-        .subcommand(
-            SubCommand::with_name("danger").arg(
-                Arg::with_name("directory")
-                    .short("d")
-                    .value_name("DIRECTORY")
-                    .takes_value(true)
-                    .help("search directory for unsafe lines"),
-            ),
-        )
-        .get_matches();
+impl UnsafeLines {
+    /// the code of the targets that get built, or of all of them
+    fn built_targets(&self, all_targets: bool) -> impl Iterator<Item = (TargetKind, &UnsafeCode)> {
+        built_targets(&self.targets, all_targets)
+    }
 
-    if let Some(matches) = matches.subcommand_matches("danger") {
-        if matches.is_present("directory") {
-            // if directory option was used.
-            let path = matches.value_of("directory").unwrap();
-            println!("{} unsafe lines", count_of_unsafe(Path::new(path), true)?);
-        } else {
-            let mut config = Config::default().expect("No idea why this would fail");
-            let packs = print_files(&mut config)?;
-            for p in packs {
-                let UnsafeLines { package, lines } = p;
-                let name = package.package_id().name();
-                if lines > 0 {
-                    println!("{}, {}", name, lines);
-                }
+    /// unsafe lines over the targets that get built, or all of them
+    fn line_count(&self, all_targets: bool) -> usize {
+        self.built_targets(all_targets)
+            .map(|(_, code)| code.lines.len())
+            .sum()
+    }
+
+    /// the unsafe lines, declarations and operations per category, over the
+    /// same targets
+    fn counts(&self, all_targets: bool) -> BTreeMap<&'static str, usize> {
+        let mut counts = BTreeMap::new();
+        for (_, code) in self.built_targets(all_targets) {
+            *counts.entry("unsafe_lines").or_insert(0) += code.lines.len();
+            *counts.entry("unsafe_declarations").or_insert(0) += code.declarations.len();
+            for (category, count) in code.category_counts() {
+                *counts.entry(category.name()).or_insert(0) += count;
             }
         }
-    } else {
-        panic!("run tool with \"cargo danger\" or \"cargo danger PATH\"");
+        counts
     }
-    Ok(())
 }
 
-fn main() -> Result<()> {
-    parse_input()
+fn app() -> App<'static, 'static> {
+    App::new("cargo-danger")
+        .version("1.0")
+        .about("Detect unsafe code")
+        .subcommand(
+            SubCommand::with_name("danger")
+                .arg(
+                    Arg::with_name("path")
+                        .value_name("PATH")
+                        .index(1)
+                        .help("a cargo project to analyze along with its dependencies, or a plain directory to search for unsafe lines"),
+                )
+                .arg(
+                    Arg::with_name("manifest-path")
+                        .global(true)
+                        .long("manifest-path")
+                        .value_name("PATH")
+                        .takes_value(true)
+                        .help("the Cargo.toml of the project to analyze"),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .global(true)
+                        .long("format")
+                        .value_name("FORMAT")
+                        .takes_value(true)
+                        .possible_values(&["text", "json", "sarif", "html", "markdown"])
+                        .default_value("text")
+                        .help("how to print the report"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .global(true)
+                        .short("o")
+                        .long("output")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .help("write the report to FILE instead of printing it, for any format but text"),
+                )
+                .arg(
+                    Arg::with_name("baseline")
+                        .global(true)
+                        .long("baseline")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .help("a report saved earlier with baseline save or --format json, for check and --format markdown to compare against"),
+                )
+                .arg(
+                    Arg::with_name("ledger")
+                        .global(true)
+                        .long("ledger")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .help("the ledger of reviewed unsafe sites, danger-reviews.toml at the workspace root by default"),
+                )
+                .arg(
+                    Arg::with_name("directory")
+                        .short("d")
+                        .value_name("DIRECTORY")
+                        .takes_value(true)
+                        .help("search directory for unsafe lines"),
+                )
+                .arg(
+                    Arg::with_name("verbose")
+                        .short("v")
+                        .long("verbose")
+                        .help("print where every unsafe line is, along with its source"),
+                )
+                .arg(
+                    Arg::with_name("ffi")
+                        .long("ffi")
+                        .help("list the FFI surface instead: extern blocks, link attributes, extern fns and exported symbols"),
+                )
+                .arg(
+                    Arg::with_name("all-targets")
+                        .global(true)
+                        .long("all-targets")
+                        .help("also report tests, examples and benches, which never make it into a build"),
+                )
+                .arg(
+                    Arg::with_name("build-deps")
+                        .global(true)
+                        .long("build-deps")
+                        .help("also follow build dependencies, which run code at compile time"),
+                )
+                .arg(
+                    Arg::with_name("dev-deps")
+                        .global(true)
+                        .long("dev-deps")
+                        .help("also follow dev dependencies, which only tests, examples and benches use"),
+                )
+                .arg(
+                    Arg::with_name("dep-kinds")
+                        .global(true)
+                        .long("dep-kinds")
+                        .value_name("KINDS")
+                        .takes_value(true)
+                        .use_delimiter(true)
+                        .possible_values(&["normal", "build", "dev"])
+                        .help("the kinds of dependency to report, e.g. normal,build,dev"),
+                )
+                .arg(
+                    Arg::with_name("depth")
+                        .global(true)
+                        .long("depth")
+                        .value_name("N")
+                        .takes_value(true)
+                        .validator(|n| n.parse::<usize>().map(|_| ()).map_err(|e| e.to_string()))
+                        .help("only go N dependencies deep, so 1 means direct dependencies only"),
+                )
+                .arg(
+                    Arg::with_name("features")
+                        .global(true)
+                        .long("features")
+                        .value_name("FEATURES")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("features of the root package to turn on, as for cargo build"),
+                )
+                .arg(
+                    Arg::with_name("all-features")
+                        .global(true)
+                        .long("all-features")
+                        .help("turn on every feature of the root package"),
+                )
+                .arg(
+                    Arg::with_name("no-default-features")
+                        .global(true)
+                        .long("no-default-features")
+                        .help("don't turn on the root package's default features"),
+                )
+                .arg(
+                    Arg::with_name("workspace")
+                        .global(true)
+                        .long("workspace")
+                        .help("analyze every member of the workspace"),
+                )
+                .arg(
+                    Arg::with_name("package")
+                        .global(true)
+                        .short("p")
+                        .long("package")
+                        .value_name("MEMBER")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("analyze this workspace member, can be given more than once"),
+                )
+                .arg(
+                    Arg::with_name("exclude")
+                        .global(true)
+                        .long("exclude")
+                        .value_name("MEMBER")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("leave this workspace member out, along with --workspace"),
+                )
+                .arg(
+                    Arg::with_name("target")
+                        .global(true)
+                        .long("target")
+                        .value_name("TRIPLE")
+                        .takes_value(true)
+                        .help("evaluate #[cfg] for this target triple instead of the host"),
+                )
+                .arg(
+                    Arg::with_name("orphans")
+                        .long("orphans")
+                        .help("list the .rs files of each package that none of its targets use"),
+                )
+                .subcommand(SubCommand::with_name("tree").about(
+                    "print the dependency tree with the unsafe lines of each package and of its subtree",
+                ))
+                .subcommand(
+                    SubCommand::with_name("why")
+                        .about("print every path from the root to a crate, and the unsafe lines along them")
+                        .arg(
+                            Arg::with_name("crate")
+                                .value_name("CRATE")
+                                .required(true)
+                                .help("the name of the crate to explain"),
+                        )
+                        .arg(
+                            Arg::with_name("limit")
+                                .long("limit")
+                                .value_name("N")
+                                .takes_value(true)
+                                .validator(|n| n.parse::<usize>().map(|_| ()).map_err(|e| e.to_string()))
+                                .help("stop at N paths, but still print the shortest path through each direct dependency that leads to the crate"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("baseline")
+                        .about("save the unsafe code found now, for check to compare against later")
+                        .setting(AppSettings::SubcommandRequiredElseHelp)
+                        .subcommand(
+                            SubCommand::with_name("save")
+                                .about("write the json report to FILE")
+                                .arg(
+                                    Arg::with_name("file")
+                                        .value_name("FILE")
+                                        .required(true)
+                                        .help("where to save the baseline, e.g. danger-baseline.json"),
+                                ),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("diff")
+                        .about("compare the unsafe code of the dependencies locked in two Cargo.lock files")
+                        .arg(
+                            Arg::with_name("old")
+                                .long("old")
+                                .value_name("LOCKFILE")
+                                .takes_value(true)
+                                .required_unless("old-rev")
+                                .conflicts_with("old-rev")
+                                .help("the Cargo.lock to compare against, e.g. Cargo.lock.orig"),
+                        )
+                        .arg(
+                            Arg::with_name("old-rev")
+                                .long("old-rev")
+                                .value_name("REV")
+                                .takes_value(true)
+                                .help("compare against the Cargo.lock of this git revision instead"),
+                        )
+                        .arg(
+                            Arg::with_name("new")
+                                .long("new")
+                                .value_name("LOCKFILE")
+                                .takes_value(true)
+                                .help("the Cargo.lock with the new versions, the workspace's own by default"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about("print every unsafe site with its source, like grep")
+                        .arg(
+                            Arg::with_name("crate")
+                                .value_name("CRATE")
+                                .help("only list the sites of this crate"),
+                        )
+                        .arg(
+                            Arg::with_name("context")
+                                .short("C")
+                                .long("context")
+                                .value_name("N")
+                                .takes_value(true)
+                                .default_value("0")
+                                .validator(|n| n.parse::<usize>().map(|_| ()).map_err(|e| e.to_string()))
+                                .help("print N lines of source before and after each site"),
+                        )
+                        .arg(
+                            Arg::with_name("quickfix")
+                                .long("quickfix")
+                                .help("print one file:line:col: message line per site instead, for an editor's quickfix list"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("crate-diff")
+                        .about("show how the unsafe code of a crate changed between two versions in the registry source cache")
+                        .arg(
+                            Arg::with_name("crate")
+                                .value_name("CRATE")
+                                .required(true)
+                                .help("the name of the crate"),
+                        )
+                        .arg(
+                            Arg::with_name("old-version")
+                                .value_name("OLD_VERSION")
+                                .required(true)
+                                .help("the version to compare against, e.g. 0.4.7"),
+                        )
+                        .arg(
+                            Arg::with_name("new-version")
+                                .value_name("NEW_VERSION")
+                                .required(true)
+                                .help("the version to compare, e.g. 0.5.1"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("review")
+                        .about("list the unsafe sites that nobody has reviewed or that changed since, or record them as reviewed in the ledger")
+                        .arg(
+                            Arg::with_name("fingerprint")
+                                .value_name("FINGERPRINT")
+                                .multiple(true)
+                                .requires_all(&["reviewer", "note"])
+                                .help("the fingerprints of the sites to record, as review lists them"),
+                        )
+                        .arg(
+                            Arg::with_name("all")
+                                .long("all")
+                                .conflicts_with("fingerprint")
+                                .requires_all(&["reviewer", "note"])
+                                .help("record every site that's left to review"),
+                        )
+                        .arg(
+                            Arg::with_name("crate")
+                                .long("crate")
+                                .value_name("CRATE")
+                                .takes_value(true)
+                                .help("only list or record the sites of this crate"),
+                        )
+                        .arg(
+                            Arg::with_name("reviewer")
+                                .long("reviewer")
+                                .value_name("NAME")
+                                .takes_value(true)
+                                .help("who reviewed the sites"),
+                        )
+                        .arg(
+                            Arg::with_name("note")
+                                .long("note")
+                                .value_name("TEXT")
+                                .takes_value(true)
+                                .help("why the sites are sound"),
+                        ),
+                )
+                .subcommand(SubCommand::with_name("check").about(
+                    "fail when a package breaks the danger.toml policy, or has more unsafe code than in --baseline",
+                )),
+        )
 }
 
-fn print_files(config: &mut Config) -> Result<Vec<UnsafeLines>> {
-    let root = resolve_roots(config)?;
-    let mut packages = vec![];
-    // TODO: be able to choose first order vs all deps
-    let _packages = resolve_packages(config, root)?;
-    let mut open_files = true;
-    for p in _packages {
-        let things = count_of_unsafe(&p.root(), open_files)?;
-        open_files = true;
-        packages.push(UnsafeLines {
-            package: p,
-            lines: things,
-        });
+fn parse_input() -> Result<()> {
+    let matches = app().get_matches();
+    let matches = match matches.subcommand_matches("danger") {
+        Some(matches) => matches,
+        None => panic!("run tool with \"cargo danger\" or \"cargo danger PATH\""),
+    };
+    let verbose = matches.is_present("verbose");
+    let ffi = matches.is_present("ffi");
+    let orphans = matches.is_present("orphans");
+    let tree = matches.subcommand_matches("tree");
+    let why = matches.subcommand_matches("why");
+    let baseline_matches = matches.subcommand_matches("baseline");
+    let save = baseline_matches.and_then(|m| m.subcommand_matches("save"));
+    let check = matches.subcommand_matches("check");
+    let diff = matches.subcommand_matches("diff");
+    let review = matches.subcommand_matches("review");
+    let crate_diff = matches.subcommand_matches("crate-diff");
+    let list = matches.subcommand_matches("list");
+    // global options can come before or after a subcommand, and only show
+    // up on the side they were given
+    let globals: Vec<_> = tree
+        .or(why)
+        .or(save)
+        .or(check)
+        .or(diff)
+        .or(review)
+        .or(crate_diff)
+        .or(list)
+        .into_iter()
+        .chain(baseline_matches)
+        .chain(Some(matches))
+        .collect();
+    let is_present = |name| globals.iter().any(|m| m.is_present(name));
+    let value_of = |name| globals.iter().find_map(|m| m.value_of(name));
+    let all_targets = is_present("all-targets");
+    let format = globals
+        .iter()
+        .filter(|m| m.occurrences_of("format") > 0)
+        .find_map(|m| m.value_of("format"))
+        .unwrap_or("text");
+    let output = value_of("output");
+    if output.is_some() && format == "text" {
+        return Err("--output needs --format json, sarif, html or markdown".into());
     }
-    Ok(packages)
-}
-
-fn count_if_in(in_unsafe_block: bool) -> usize {
-    if in_unsafe_block {
-        1
-    } else {
-        0
+    let mut config = Config::default().expect("No idea why this would fail");
+    let baseline = match value_of("baseline") {
+        Some(path) => Some(Baseline::load(&config.cwd().join(path))?),
+        None => None,
+    };
+    let target = value_of("target");
+    if let Some(crate_diff) = crate_diff {
+        // neither version has to be in the project, or there be a project
+        let name = crate_diff.value_of("crate").unwrap();
+        let version =
+            |arg| cached_version(&config, name, crate_diff.value_of(arg).unwrap(), target);
+        let (old, new) = (version("old-version")?, version("new-version")?);
+        crate_diff::print_crate_diff(name, &old, &new, all_targets);
+        return Ok(());
     }
-}
-
-fn unsafe_things_of_block(block: &syn::Block, in_unsafe_block: bool) -> usize {
-    let mut total = 0;
-    for s in &block.stmts {
-        total += unsafe_things_of_statement(&s, in_unsafe_block);
+    let mut dep_kinds: BTreeSet<DepKind> =
+        match globals.iter().find_map(|m| m.values_of("dep-kinds")) {
+            Some(names) => names.filter_map(DepKind::from_name).collect(),
+            None => vec![DepKind::Normal].into_iter().collect(),
+        };
+    if is_present("build-deps") {
+        dep_kinds.insert(DepKind::Build);
     }
-    total
-}
-
-fn unsafe_things_of_expression(expr: &syn::Expr, in_unsafe_block: bool) -> usize {
-    use syn::Expr;
-    let result: usize = match expr {
-        Expr::Box(boxed_expr) => unsafe_things_of_expression(&boxed_expr.expr, in_unsafe_block),
-        Expr::InPlace(in_place_expr) => {
-            let left = unsafe_things_of_expression(&in_place_expr.place, in_unsafe_block);
-            let right = unsafe_things_of_expression(&in_place_expr.value, in_unsafe_block);
-            left + right
-        }
-        Expr::Array(_array_exp) => {
-            // TODO: i'm not sure how to accumulate individual potential expressions
-            // in here so i'm just going to count an array expression as one.
-            count_if_in(in_unsafe_block)
-        }
-        Expr::Call(_expr) => {
-            /*
-            /// A function call expression: `invoke(a, b)`.
-            ///
-            /// *This type is available if Syn is built with the `"derive"` or
-            /// `"full"` feature.*
-            pub Call(ExprCall {
-                pub attrs: Vec<Attribute>,
-                pub func: Box<Expr>,
-                pub paren_token: token::Paren,
-                pub args: Punctuated<Expr, Token![,]>,
-            }),
-            */
-            count_if_in(in_unsafe_block)
-        }
-        Expr::MethodCall(_expr) => {
-            /*
-            /// A method call expression: `x.foo::<T>(a, b)`.
-            ///
-            /// *This type is available if Syn is built with the `"full"` feature.*
-            pub MethodCall(ExprMethodCall #full {
-            pub attrs: Vec<Attribute>,
-            pub receiver: Box<Expr>,
-            pub dot_token: Token![.],
-            pub method: Ident,
-            pub turbofish: Option<MethodTurbofish>,
-            pub paren_token: token::Paren,
-            pub args: Punctuated<Expr, Token![,]>,
-            }),
-            */
-            count_if_in(in_unsafe_block)
-        }
-        Expr::Tuple(_expr) => {
-            /*
-            /// A tuple expression: `(a, b, c, d)`.
-            ///
-            /// *This type is available if Syn is built with the `"full"` feature.*
-            pub Tuple(ExprTuple #full {
-                pub attrs: Vec<Attribute>,
-                pub paren_token: token::Paren,
-                pub elems: Punctuated<Expr, Token![,]>,
-            }),
-            */
-            count_if_in(in_unsafe_block)
-        }
-        Expr::Binary(_expr) => {
-            /*
-            /// A binary operation: `a + b`, `a * b`.
-            ///
-            /// *This type is available if Syn is built with the `"derive"` or
-            /// `"full"` feature.*
-            pub Binary(ExprBinary {
-                pub attrs: Vec<Attribute>,
-                pub left: Box<Expr>,
-                pub op: BinOp,
-                pub right: Box<Expr>,
-            }),
-            */
-            count_if_in(in_unsafe_block)
-        }
-
-        Expr::Unary(_expr) => {
-            /*
-            /// A unary operation: `!x`, `*x`.
-            ///
-            /// *This type is available if Syn is built with the `"derive"` or
-            /// `"full"` feature.*
-            pub Unary(ExprUnary {
-                pub attrs: Vec<Attribute>,
-                pub op: UnOp,
-                pub expr: Box<Expr>,
-            }),
-            */
-            count_if_in(in_unsafe_block)
-        }
-
-        Expr::Lit(_expr) => {
-            /*
-            /// A literal in place of an expression: `1`, `"foo"`.
-            ///
-            /// *This type is available if Syn is built with the `"derive"` or
-            /// `"full"` feature.*
-            pub Lit(ExprLit {
-                pub attrs: Vec<Attribute>,
-                pub lit: Lit,
-            }),
-            */
-            count_if_in(in_unsafe_block)
-        }
-
-        Expr::Cast(_expr) => {
-            /*
-            /// A cast expression: `foo as f64`.
-            ///
-            /// *This type is available if Syn is built with the `"derive"` or
-            /// `"full"` feature.*
-            pub Cast(ExprCast {
-                pub attrs: Vec<Attribute>,
-                pub expr: Box<Expr>,
-                pub as_token: Token![as],
-                pub ty: Box<Type>,
-            }),
-            */
-            count_if_in(in_unsafe_block)
-        }
-
-        Expr::Type(_expr) => {
-            /*
-            /// A type ascription expression: `foo: f64`.
-            ///
-            /// *This type is available if Syn is built with the `"full"` feature.*
-            pub Type(ExprType #full {
-                pub attrs: Vec<Attribute>,
-                pub expr: Box<Expr>,
-                pub colon_token: Token![:],
-                pub ty: Box<Type>,
-            }),
-            */
-            count_if_in(in_unsafe_block)
-        }
-
-        Expr::Let(_expr) => {
-            /*
-            /// A `let` guard: `let Some(x) = opt`.
-            ///
-            /// *This type is available if Syn is built with the `"full"` feature.*
-            pub Let(ExprLet #full {
-                pub attrs: Vec<Attribute>,
-                pub let_token: Token![let],
-                pub pats: Punctuated<Pat, Token![|]>,
-                pub eq_token: Token![=],
-                pub expr: Box<Expr>,
-            }),
-            */
-            count_if_in(in_unsafe_block)
-        }
-        Expr::If(expr) => {
-            let cond_result = unsafe_things_of_expression(&expr.cond, in_unsafe_block);
-            let then_branch = unsafe_things_of_block(&expr.then_branch, in_unsafe_block);
-            match &expr.else_branch {
-                Some((_, expr)) => {
-                    let else_result = unsafe_things_of_expression(&expr, in_unsafe_block);
-                    cond_result + then_branch + else_result
+    if is_present("dev-deps") {
+        dep_kinds.insert(DepKind::Dev);
+    }
+    let values_of = |name| -> Vec<String> {
+        globals
+            .iter()
+            .find_map(|m| m.values_of(name))
+            .map(|values| values.map(String::from).collect())
+            .unwrap_or_default()
+    };
+    let selection = MemberSelection {
+        workspace: is_present("workspace"),
+        packages: values_of("package"),
+        exclude: values_of("exclude"),
+    };
+    let options = ResolveOptions {
+        dep_kinds,
+        max_depth: value_of("depth").map(|n| n.parse().unwrap()),
+        features: values_of("features"),
+        all_features: is_present("all-features"),
+        no_default_features: is_present("no-default-features"),
+        // diff takes the new versions from --new, when it's given
+        lockfile: match diff.and_then(|diff| diff.value_of("new")) {
+            Some(new) => Some(read_lockfile(&config.cwd().join(new))?),
+            None => None,
+        },
+        // or else from the workspace's own Cargo.lock, as it is
+        locked: diff.is_some(),
+    };
+    let (directory, manifest_path) = match value_of("manifest-path") {
+        Some(manifest_path) => (None, Some(config.cwd().join(manifest_path))),
+        None => match matches.value_of("path") {
+            // a cargo project gets its dependencies analyzed, anything
+            // else is just searched
+            Some(path) => {
+                let path = config.cwd().join(path);
+                if path.file_name() == Some("Cargo.toml".as_ref()) {
+                    (None, Some(path))
+                } else if path.join("Cargo.toml").is_file() {
+                    (None, Some(path.join("Cargo.toml")))
+                } else {
+                    (Some(path), None)
                 }
-                None => cond_result + then_branch,
             }
-        }
-        Expr::While(_expr) => {
-            /*
-            /// A while loop: `while expr { ... }`.
-            ///
-            /// *This type is available if Syn is built with the `"full"` feature.*
-            pub While(ExprWhile #full {
-                pub attrs: Vec<Attribute>,
-                pub label: Option<Label>,
-                pub while_token: Token![while],
-                pub cond: Box<Expr>,
-                pub body: Block,
-            }),
-            */
-            count_if_in(in_unsafe_block)
-        }
-
-        Expr::ForLoop(_expr) => {
-            /*
-            /// A for loop: `for pat in expr { ... }`.
-            ///
-            /// *This type is available if Syn is built with the `"full"` feature.*
-            pub ForLoop(ExprForLoop #full {
-                pub attrs: Vec<Attribute>,
-                pub label: Option<Label>,
-                pub for_token: Token![for],
-                pub pat: Box<Pat>,
-                pub in_token: Token![in],
-                pub expr: Box<Expr>,
-                pub body: Block,
-            }),
-            */
-            count_if_in(in_unsafe_block)
-        }
-
-        Expr::Loop(_expr) => {
-            /*
-            /// Conditionless loop: `loop { ... }`.
-            ///
-            /// *This type is available if Syn is built with the `"full"` feature.*
-            pub Loop(ExprLoop #full {
-                pub attrs: Vec<Attribute>,
-                pub label: Option<Label>,
-                pub loop_token: Token![loop],
-                pub body: Block,
-            }),
-            */
-            count_if_in(in_unsafe_block)
-        }
-
-        Expr::Match(_expr) => {
-            /*
-            /// A `match` expression: `match n { Some(n) => {}, None => {} }`.
-            ///
-            /// *This type is available if Syn is built with the `"full"` feature.*
-            pub Match(ExprMatch #full {
-                pub attrs: Vec<Attribute>,
-                pub match_token: Token![match],
-                pub expr: Box<Expr>,
-                pub brace_token: token::Brace,
-                pub arms: Vec<Arm>,
-            }),
-            */
-            count_if_in(in_unsafe_block)
-        }
+            None => (
+                matches
+                    .value_of("directory")
+                    .map(|directory| config.cwd().join(directory)),
+                None,
+            ),
+        },
+    };
+    let cwd = config.cwd().to_path_buf();
+    let print_list = |sites, list: &ArgMatches| {
+        let context = list.value_of("context").unwrap().parse().unwrap();
+        list::print_list(sites, &cwd, list.is_present("quickfix"), context);
+    };
 
-        Expr::Closure(_expr) => {
-            /*
-            /// A closure expression: `|a, b| a + b`.
-            ///
-            /// *This type is available if Syn is built with the `"full"` feature.*
-            pub Closure(ExprClosure #full {
-                pub attrs: Vec<Attribute>,
-                pub asyncness: Option<Token![async]>,
-                pub movability: Option<Token![static]>,
-                pub capture: Option<Token![move]>,
-                pub or1_token: Token![|],
-                pub inputs: Punctuated<FnArg, Token![,]>,
-                pub or2_token: Token![|],
-                pub output: ReturnType,
-                pub body: Box<Expr>,
-            }),
-            */
-            count_if_in(in_unsafe_block)
+    if let Some(path) = directory {
+        if !path.is_dir() {
+            return Err(format!("{} isn't a directory", path.display()).into());
         }
-
-        Expr::Unsafe(expr) => {
-            /*
-            /// An unsafe block: `unsafe { ... }`.
-            ///
-            /// *This type is available if Syn is built with the `"full"` feature.*
-            pub Unsafe(ExprUnsafe #full {
-                pub attrs: Vec<Attribute>,
-                pub unsafe_token: Token![unsafe],
-                pub block: Block,
-            }),
-            */
-            let mut total = 0;
-            for s in &expr.block.stmts {
-                total += unsafe_things_of_statement(&s, true);
-            }
-            total
+        // there's no package to take features from, so only the target's
+        // cfg values apply
+        let cfg = CfgSet::for_target(&config, target)?;
+        if diff.is_some() {
+            return Err("diff needs a cargo project, not a directory".into());
         }
-
-        Expr::Block(expr) => {
-            /*
-            /// A blocked scope: `{ ... }`.
-            ///
-            /// *This type is available if Syn is built with the `"full"` feature.*
-            pub Block(ExprBlock #full {
-                pub attrs: Vec<Attribute>,
-                pub label: Option<Label>,
-                pub block: Block,
-            }),
-            */
-            let mut total = 0;
-            for s in &expr.block.stmts {
-                total += unsafe_things_of_statement(&s, in_unsafe_block);
-            }
-            total
+        if review.is_some() {
+            // the ledger goes by crate
+            return Err("review needs a cargo project, not a directory".into());
         }
-
-        Expr::Assign(_expr) => {
-            /*
-            /// An assignment expression: `a = compute()`.
-            ///
-            /// *This type is available if Syn is built with the `"full"` feature.*
-            pub Assign(ExprAssign #full {
-                pub attrs: Vec<Attribute>,
-                pub left: Box<Expr>,
-                pub eq_token: Token![=],
-                pub right: Box<Expr>,
-            }),
-            */
-            count_if_in(in_unsafe_block)
+        if tree.is_some() {
+            return Err("tree needs a cargo project, not a directory".into());
         }
-
-        Expr::AssignOp(_expr) => {
-            /*
-            /// A compound assignment expression: `counter += 1`.
-            ///
-            /// *This type is available if Syn is built with the `"full"` feature.*
-            pub AssignOp(ExprAssignOp #full {
-                pub attrs: Vec<Attribute>,
-                pub left: Box<Expr>,
-                pub op: BinOp,
-                pub right: Box<Expr>,
-            }),
-            */
-            count_if_in(in_unsafe_block)
+        if why.is_some() {
+            return Err("why CRATE needs a cargo project, not a directory".into());
         }
-
-        Expr::Field(_expr) => {
-            /*
-            /// Access of a named struct field (`obj.k`) or unnamed tuple struct
-            /// field (`obj.0`).
-            ///
-            /// *This type is available if Syn is built with the `"full"` feature.*
-            pub Field(ExprField {
-                pub attrs: Vec<Attribute>,
-                pub base: Box<Expr>,
-                pub dot_token: Token![.],
-                pub member: Member,
-            }),
-            */
-            count_if_in(in_unsafe_block)
-        }
-
-        Expr::Index(_expr) => {
-            /*
-            /// A square bracketed indexing expression: `vector[2]`.
-            ///
-            /// *This type is available if Syn is built with the `"derive"` or
-            /// `"full"` feature.*
-            pub Index(ExprIndex {
-                pub attrs: Vec<Attribute>,
-                pub expr: Box<Expr>,
-                pub bracket_token: token::Bracket,
-                pub index: Box<Expr>,
-            }),
-
-            */
-            count_if_in(in_unsafe_block)
+        if orphans {
+            // there are no targets for a file to be left out of
+            return Err("--orphans needs a cargo project, not a directory".into());
         }
-
-        Expr::Range(_expr) => {
-            /*
-            /// A range expression: `1..2`, `1..`, `..2`, `1..=2`, `..=2`.
-            ///
-            /// *This type is available if Syn is built with the `"full"` feature.*
-            pub Range(ExprRange #full {
-                pub attrs: Vec<Attribute>,
-                pub from: Option<Box<Expr>>,
-                pub limits: RangeLimits,
-                pub to: Option<Box<Expr>>,
-            }),
-            */
-            count_if_in(in_unsafe_block)
+        let code = count_of_unsafe(&path, &cfg, true)?;
+        if let Some(list) = list {
+            if list.is_present("crate") {
+                return Err("list CRATE needs a cargo project, not a directory".into());
+            }
+            print_list(code.sites(), list);
+            return Ok(());
         }
-
-        Expr::Path(_expr) => {
-            /*
-            /// A path like `std::mem::replace` possibly containing generic
-            /// parameters and a qualified self-type.
-            ///
-            /// A plain identifier like `x` is a path of length 1.
-            ///
-            /// *This type is available if Syn is built with the `"derive"` or
-            /// `"full"` feature.*
-            pub Path(ExprPath {
-                pub attrs: Vec<Attribute>,
-                pub qself: Option<QSelf>,
-                pub path: Path,
-            }),
-            */
-            count_if_in(in_unsafe_block)
+        if let Some(save) = save {
+            let report = json::render_directory(config.cwd(), &path, &code)?;
+            return write_report(&config, save.value_of("file"), &report);
         }
-
-        Expr::Reference(_expr) => {
-            /*
-            /// A referencing operation: `&a` or `&mut a`.
-            ///
-            /// *This type is available if Syn is built with the `"full"` feature.*
-            pub Reference(ExprReference #full {
-                pub attrs: Vec<Attribute>,
-                pub and_token: Token![&],
-                pub mutability: Option<Token![mut]>,
-                pub expr: Box<Expr>,
-            }),
-            */
-            count_if_in(in_unsafe_block)
+        if check.is_some() {
+            // there are no crates for a policy to go by
+            let baseline = baseline.ok_or("check needs --baseline FILE")?;
+            let current = Baseline::parse(&json::render_directory(config.cwd(), &path, &code)?)?;
+            if !baseline.check(&current) {
+                return Err("unsafe code grew since the baseline".into());
+            }
+            return Ok(());
         }
-
-        Expr::Break(_expr) => {
-            /*
-            /// A `break`, with an optional label to break and an optional
-            /// expression.
-            ///
-            /// *This type is available if Syn is built with the `"full"` feature.*
-            pub Break(ExprBreak #full {
-                pub attrs: Vec<Attribute>,
-                pub break_token: Token![break],
-                pub label: Option<Lifetime>,
-                pub expr: Option<Box<Expr>>,
-            }),
-            */
-            count_if_in(in_unsafe_block)
+        if format != "text" {
+            let report = match format {
+                "json" => json::render_directory(config.cwd(), &path, &code)?,
+                "sarif" => sarif::render_directory(config.cwd(), &code)?,
+                "html" => html::render_directory(&path, &code),
+                _ => markdown::render_directory(&path, &code, baseline.as_ref()),
+            };
+            return write_report(&config, output, &report);
         }
-
-        Expr::Continue(_expr) => {
-            /*
-            /// A `continue`, with an optional label.
-            ///
-            /// *This type is available if Syn is built with the `"full"` feature.*
-            pub Continue(ExprContinue #full {
-                pub attrs: Vec<Attribute>,
-                pub continue_token: Token![continue],
-                pub label: Option<Lifetime>,
-            }),
-            */
-            count_if_in(in_unsafe_block)
+        if ffi {
+            text::print_ffi(&code);
+        } else {
+            text::print_directory(&code, verbose);
         }
+        return Ok(());
+    }
 
-        Expr::Return(_expr) => {
-            /*
-            /// A `return`, with an optional value to be returned.
-            ///
-            /// *This type is available if Syn is built with the `"full"` feature.*
-            pub Return(ExprReturn #full {
-                pub attrs: Vec<Attribute>,
-                pub return_token: Token![return],
-                pub expr: Option<Box<Expr>>,
-            }),
-            */
-            count_if_in(in_unsafe_block)
+    let (root, graph, packs) = print_files(
+        &mut config,
+        manifest_path.as_deref(),
+        target,
+        &selection,
+        &options,
+        orphans,
+    )?;
+    let own: HashMap<NodeIndex, usize> = packs
+        .iter()
+        .map(|p| (p.node, p.line_count(all_targets)))
+        .collect();
+    if tree.is_some() {
+        print_tree(&graph, &own);
+        return Ok(());
+    }
+    if let Some(why) = why {
+        let limit = why.value_of("limit").map(|n| n.parse().unwrap());
+        return print_why(&graph, why.value_of("crate").unwrap(), &own, limit);
+    }
+    let packs = listed(packs, &options.dep_kinds);
+    if let Some(diff) = diff {
+        let old = match diff.value_of("old") {
+            Some(old) => Old::Lockfile(config.cwd().join(old)),
+            // the old revision's manifests go with its Cargo.lock
+            None => Old::Rev(diff.value_of("old-rev").unwrap()),
+        };
+        let old = diff::old_packages(
+            &mut config,
+            &root,
+            manifest_path.as_deref(),
+            old,
+            target,
+            &selection,
+            &options,
+        )?;
+        diff::print_diff(&listed(old, &options.dep_kinds), &packs, all_targets);
+        return Ok(());
+    }
+    let ledger_path = match value_of("ledger") {
+        Some(path) => config.cwd().join(path),
+        None => root.join(LEDGER_FILE),
+    };
+    if let Some(list) = list {
+        let packs = named(packs, list.value_of("crate"))?;
+        let sites = packs
+            .iter()
+            .flat_map(|p| unique_sites(p.built_targets(all_targets).map(|(_, code)| code)))
+            .collect();
+        print_list(sites, list);
+        return Ok(());
+    }
+    if let Some(review) = review {
+        let packs = named(packs, review.value_of("crate"))?;
+        let fingerprints: Vec<&str> = review
+            .values_of("fingerprint")
+            .map(|values| values.collect())
+            .unwrap_or_default();
+        if fingerprints.is_empty() && !review.is_present("all") {
+            Ledger::load(&ledger_path)?.print_pending(&packs, all_targets);
+            return Ok(());
         }
+        return record_reviews(
+            &ledger_path,
+            &packs,
+            all_targets,
+            &fingerprints,
+            review.value_of("reviewer").unwrap(),
+            review.value_of("note").unwrap(),
+        );
+    }
+    if let Some(save) = save {
+        let report = json::render_packages(&packs, all_targets)?;
+        return write_report(&config, save.value_of("file"), &report);
+    }
+    if check.is_some() {
+        return check_packages(&root, &packs, all_targets, baseline);
+    }
+    if format != "text" {
+        let report = match format {
+            "json" => json::render_packages(&packs, all_targets)?,
+            "sarif" => sarif::render_packages(config.cwd(), &packs, all_targets)?,
+            "html" => html::render_packages(&packs, all_targets),
+            _ => markdown::render_packages(&packs, all_targets, baseline.as_ref()),
+        };
+        return write_report(&config, output, &report);
+    }
+    let several_members = graph.roots.len() > 1;
+    if orphans {
+        text::print_orphans(&packs, several_members);
+    } else if ffi {
+        text::print_packages_ffi(&packs, several_members, all_targets);
+    } else {
+        // -v leaves out what's been reviewed
+        let ledger = if verbose {
+            Ledger::load(&ledger_path)?
+        } else {
+            Ledger::default()
+        };
+        text::print_packages(&packs, several_members, all_targets, verbose, &ledger);
+    }
+    Ok(())
+}
 
-        Expr::Macro(_expr) => {
-            /*
-            /// A macro invocation expression: `format!("{}", q)`.
-            ///
-            /// *This type is available if Syn is built with the `"full"` feature.*
-            pub Macro(ExprMacro #full {
-                pub attrs: Vec<Attribute>,
-                pub mac: Macro,
-            }),
-            */
-            count_if_in(in_unsafe_block)
-        }
+/// everything reached gets counted, for the sake of the tree, but only the
+/// kinds of dependency asked for get listed
+fn listed(packs: Vec<UnsafeLines>, dep_kinds: &BTreeSet<DepKind>) -> Vec<UnsafeLines> {
+    packs
+        .into_iter()
+        .filter(|p| p.depth == 0 || !p.dep_kinds.is_disjoint(dep_kinds))
+        .collect()
+}
 
-        Expr::Struct(_expr) => {
-            /*
-            /// A struct literal expression: `Point { x: 1, y: 1 }`.
-            ///
-            /// The `rest` provides the value of the remaining fields as in `S { a:
-            /// 1, b: 1, ..rest }`.
-            ///
-            /// *This type is available if Syn is built with the `"full"` feature.*
-            pub Struct(ExprStruct #full {
-                pub attrs: Vec<Attribute>,
-                pub path: Path,
-                pub brace_token: token::Brace,
-                pub fields: Punctuated<FieldValue, Token![,]>,
-                pub dot2_token: Option<Token![..]>,
-                pub rest: Option<Box<Expr>>,
-            }),
-            */
-            count_if_in(in_unsafe_block)
-        }
+/// the packages called `name`, or all of them when there's no name
+fn named(packs: Vec<UnsafeLines>, name: Option<&str>) -> Result<Vec<UnsafeLines>> {
+    let name = match name {
+        Some(name) => name,
+        None => return Ok(packs),
+    };
+    let named: Vec<UnsafeLines> = packs
+        .into_iter()
+        .filter(|p| p.package.name().as_str() == name)
+        .collect();
+    if named.is_empty() {
+        return Err(format!("{} isn't in the dependency graph", name).into());
+    }
+    Ok(named)
+}
 
-        Expr::Repeat(_expr) => {
-            /*
-            /// An array literal constructed from one repeated element: `[0u8; N]`.
-            ///
-            /// *This type is available if Syn is built with the `"full"` feature.*
-            pub Repeat(ExprRepeat #full {
-                pub attrs: Vec<Attribute>,
-                pub bracket_token: token::Bracket,
-                pub expr: Box<Expr>,
-                pub semi_token: Token![;],
-                pub len: Box<Expr>,
-            }),
-            */
-            count_if_in(in_unsafe_block)
-        }
+fn read_lockfile(path: &Path) -> Result<String> {
+    std::fs::read_to_string(path)
+        .map_err(|e| format!("couldn't read {}: {}", path.display(), e).into())
+}
 
-        Expr::Paren(_expr) => {
-            /*
-            /// A parenthesized expression: `(a + b)`.
-            ///
-            /// *This type is available if Syn is built with the `"full"` feature.*
-            pub Paren(ExprParen {
-                pub attrs: Vec<Attribute>,
-                pub paren_token: token::Paren,
-                pub expr: Box<Expr>,
-            }),
-            */
-            count_if_in(in_unsafe_block)
-        }
+/// prints a report in any format but text, or writes it to `output`
+fn write_report(config: &Config, output: Option<&str>, report: &str) -> Result<()> {
+    match output {
+        Some(output) => std::fs::write(config.cwd().join(output), format!("{}\n", report))?,
+        None => println!("{}", report),
+    }
+    Ok(())
+}
 
-        Expr::Group(_expr) => {
-            /*
-            /// An expression contained within invisible delimiters.
-            ///
-            /// This variant is important for faithfully representing the precedence
-            /// of expressions and is related to `None`-delimited spans in a
-            /// `TokenStream`.
-            ///
-            /// *This type is available if Syn is built with the `"full"` feature.*
-            pub Group(ExprGroup #full {
-                pub attrs: Vec<Attribute>,
-                pub group_token: token::Group,
-                pub expr: Box<Expr>,
-            }),
-            */
-            count_if_in(in_unsafe_block)
-        }
+fn main() -> Result<()> {
+    parse_input()
+}
 
-        Expr::Try(_expr) => {
-            /*
-            /// A try-expression: `expr?`.
-            ///
-            /// *This type is available if Syn is built with the `"full"` feature.*
-            pub Try(ExprTry #full {
-                pub attrs: Vec<Attribute>,
-                pub expr: Box<Expr>,
-                pub question_token: Token![?],
-            }),
-            */
-            count_if_in(in_unsafe_block)
-        }
+/// the cfg values a package's targets get built with
+struct PackageCfg {
+    target: CfgSet,
+    host: CfgSet,
+}
 
-        Expr::Async(_expr) => {
-            /*
-            /// An async block: `async { ... }`.
-            ///
-            /// *This type is available if Syn is built with the `"full"` feature.*
-            pub Async(ExprAsync #full {
-                pub attrs: Vec<Attribute>,
-                pub async_token: Token![async],
-                pub capture: Option<Token![move]>,
-                pub block: Block,
-            }),
-            */
-            count_if_in(in_unsafe_block)
+impl PackageCfg {
+    fn of(&self, kind: TargetKind) -> CfgSet {
+        let cfg = if kind.runs_on_host() {
+            &self.host
+        } else {
+            &self.target
+        };
+        if kind.is_test() {
+            cfg.with_test()
+        } else {
+            cfg.clone()
         }
+    }
+}
 
-        Expr::TryBlock(_expr) => {
-            /*
-            /// A try block: `try { ... }`.
-            ///
-            /// *This type is available if Syn is built with the `"full"` feature.*
-            pub TryBlock(ExprTryBlock #full {
-                pub attrs: Vec<Attribute>,
-                pub try_token: Token![try],
-                pub block: Block,
-            }),
-            */
-            count_if_in(in_unsafe_block)
-        }
+/// `manifest_path` defaults to the Cargo.toml of the project we're in. the
+/// files no target uses are only looked for with `orphans`
+fn print_files(
+    config: &mut Config,
+    manifest_path: Option<&Path>,
+    target: Option<&str>,
+    selection: &MemberSelection,
+    options: &ResolveOptions,
+    orphans: bool,
+) -> Result<(PathBuf, DepGraph, Vec<UnsafeLines>)> {
+    let target_cfg = CfgSet::for_target(config, target)?;
+    let host_cfg = match target {
+        Some(_) => CfgSet::for_target(config, None)?,
+        None => target_cfg.clone(),
+    };
+    let manifest_path = match manifest_path {
+        Some(manifest_path) => manifest_path.to_path_buf(),
+        None => find_root_manifest_for_wd(config.cwd())?,
+    };
+    let workspace = Workspace::new(&manifest_path, config)?;
+    let roots = resolve_roots(&workspace, selection)?;
+    let (graph, resolved) = resolve_packages(&workspace, &roots, options)?;
+    // every package gets parsed before any gets walked, since calling one of
+    // a dependency's unsafe fns takes knowing what it declared
+    let mut parsed = vec![];
+    for resolved in resolved {
+        // a package that's only ever a build dependency gets built for the
+        // machine doing the build
+        let dep_kinds = &resolved.dep_kinds;
+        let only_build = dep_kinds.iter().all(|kind| *kind == DepKind::Build);
+        let target_cfg = if only_build && !dep_kinds.is_empty() {
+            &host_cfg
+        } else {
+            &target_cfg
+        };
+        let cfg = PackageCfg {
+            target: target_cfg.with_features(&resolved.features),
+            host: host_cfg.with_features(&resolved.features),
+        };
+        let (files, used) = package_files(&resolved.package, &cfg)?;
+        parsed.push((resolved, files, used));
+    }
+    let exported: HashMap<NodeIndex, HashSet<String>> = parsed
+        .iter()
+        .map(|(resolved, files, _)| {
+            let lib = files.get(&TargetKind::Lib).into_iter().flatten();
+            (resolved.node, Declared::exported(lib.map(|f| &f.ast)))
+        })
+        .collect();
 
-        Expr::Yield(_expr) => {
-            /*
-            /// A yield expression: `yield expr`.
-            ///
-            /// *This type is available if Syn is built with the `"full"` feature.*
-            pub Yield(ExprYield #full {
-                pub attrs: Vec<Attribute>,
-                pub yield_token: Token![yield],
-                pub expr: Option<Box<Expr>>,
-            }),
-            */
-            count_if_in(in_unsafe_block)
+    let mut packages = vec![];
+    for (resolved, files, used) in parsed {
+        let mut dependency_fns = HashSet::new();
+        for dep in graph.graph.neighbors(resolved.node) {
+            dependency_fns.extend(exported[&dep].iter().cloned());
         }
+        let targets = unsafe_code_of_package(&files, &dependency_fns);
+        let unused = if orphans {
+            orphans_of(&resolved.package, &used)
+        } else {
+            vec![]
+        };
+        packages.push(UnsafeLines {
+            package: resolved.package,
+            dep_kinds: resolved.dep_kinds,
+            depth: resolved.depth,
+            members: resolved.members,
+            targets,
+            orphans: unused,
+            node: resolved.node,
+        });
+    }
+    Ok((workspace.root().to_path_buf(), graph, packages))
+}
 
-        Expr::Verbatim(_expr) => {
-            /*
-            /// Tokens in expression position not interpreted by Syn.
-            ///
-            /// *This type is available if Syn is built with the `"derive"` or
-            /// `"full"` feature.*
-            pub Verbatim(ExprVerbatim #manual_extra_traits {
-                pub tts: TokenStream,
-            }),
-            */
-            count_if_in(in_unsafe_block)
-        }
-    };
-    result
+/// the unsafe code of each kind of target in a package, knowing about the
+/// unsafe fns its dependencies export
+fn unsafe_code_of_package(
+    files: &TargetFiles,
+    dependency_fns: &HashSet<String>,
+) -> BTreeMap<TargetKind, UnsafeCode> {
+    // a bin or a test can call an unsafe fn from the package's lib
+    let mut declared = declared_in(files.values().flatten());
+    declared.add_dependency_fns(dependency_fns);
+    files
+        .iter()
+        .map(|(kind, files)| (*kind, unsafe_code_of(files, &declared)))
+        .collect()
 }
 
-fn unsafe_things_of_statement(item: &syn::Stmt, in_unsafe_block: bool) -> usize {
-    match item {
-        syn::Stmt::Local(_local) => count_if_in(in_unsafe_block),
-        syn::Stmt::Expr(_expr_statement) => {
-            unsafe_things_of_expression(&_expr_statement, in_unsafe_block)
-        }
-        syn::Stmt::Semi(_expr_statement, _) => {
-            unsafe_things_of_expression(&_expr_statement, in_unsafe_block)
+type TargetFiles = BTreeMap<TargetKind, Vec<SourceFile>>;
+
+/// the files of each kind of target in a package, found by following the
+/// module tree of each target, along with every file any of them uses
+fn package_files(package: &Package, cfg: &PackageCfg) -> Result<(TargetFiles, HashSet<PathBuf>)> {
+    // a module shared between two bins only counts once, but one shared
+    // between a lib and a test counts for both
+    let mut seen: BTreeMap<TargetKind, HashSet<PathBuf>> = BTreeMap::new();
+    let mut files = TargetFiles::new();
+    for target in package.targets() {
+        if let TargetSourcePath::Path(src_path) = target.src_path() {
+            let kind = TargetKind::of(target);
+            let reached = crate_files(src_path, &cfg.of(kind), seen.entry(kind).or_default())?;
+            files.entry(kind).or_default().extend(reached);
         }
-        syn::Stmt::Item(item_statement) => unsafe_things_of_item(&item_statement, in_unsafe_block),
     }
+    Ok((files, seen.into_values().flatten().collect()))
 }
 
-fn unsafe_things_of_implitem(item: &syn::ImplItem, in_unsafe_block: bool) -> usize {
-    match item {
-        // figure it out:  https://docs.rs/syn/0.15.26/syn/enum.ImplItem.html
-        syn::ImplItem::Const(_) => 0,
-        syn::ImplItem::Method(method_impl) => {
-            let mut total = 0;
-            for s in &method_impl.block.stmts {
-                total += unsafe_things_of_statement(&s, in_unsafe_block);
+/// the .rs files of a package that aren't in `used`. its build directory and
+/// any package nested in it, like a workspace member under the root package,
+/// have their own files. a file that can't be resolved, like a dangling
+/// symlink, can't be used either
+fn orphans_of(package: &Package, used: &HashSet<PathBuf>) -> Vec<PathBuf> {
+    let mut orphans = vec![];
+    let mut dirs = vec![package.root().to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        let mut paths: Vec<PathBuf> = entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+        paths.sort();
+        for path in paths {
+            if path.is_dir() {
+                if path != package.root().join("target") && !path.join("Cargo.toml").is_file() {
+                    dirs.push(path);
+                }
+            } else if path.extension() == Some("rs".as_ref())
+                && !path.canonicalize().is_ok_and(|f| used.contains(&f))
+            {
+                orphans.push(path);
             }
-            total
-        }
-        syn::ImplItem::Type(_) => {
-            // are there unsafe types?
-            0
         }
-        syn::ImplItem::Existential(_) => 0,
-        syn::ImplItem::Macro(_) => 0,
-        syn::ImplItem::Verbatim(_) => 0,
     }
+    orphans.sort();
+    orphans
 }
 
-/// recursively count unsafe things, since items can have more items in them
-fn unsafe_things_of_item(item: &Item, in_unsafe_block: bool) -> usize {
-    let result: usize = match item {
-        Item::Fn(fn_def) => match fn_def.unsafety {
-            Some(_) => {
-                let mut total = 0;
-                for s in &fn_def.block.stmts {
-                    total += unsafe_things_of_statement(&s, true);
-                }
-                total
-            }
-            None => {
-                let mut total = 0;
-                for s in &fn_def.block.stmts {
-                    total += unsafe_things_of_statement(&s, in_unsafe_block);
-                }
-                total
-            }
-        },
-        Item::Mod(mod_def) => {
-            // TODO: i don't understand the mod_def thing
-            match &mod_def.content {
-                Some((_brace, items)) => {
-                    let mut total = 0;
-                    for i in items {
-                        total += unsafe_things_of_item(&i, in_unsafe_block);
-                    }
-                    total
-                }
-                None => 0,
-            }
-        }
-        Item::Impl(impl_def) => match impl_def.unsafety {
-            Some(_) => {
-                let mut total = 0;
-                for i in &impl_def.items {
-                    total += unsafe_things_of_implitem(&i, true);
-                }
-                total
-            }
-            None => {
-                let mut total = 0;
-                for i in &impl_def.items {
-                    total += unsafe_things_of_implitem(&i, in_unsafe_block);
+fn count_of_unsafe(root_dir: &Path, cfg: &CfgSet, open_files: bool) -> Result<UnsafeCode> {
+    let mut parsed = vec![];
+    for f in files_of(root_dir)? {
+        if let Some(ext) = f.extension() {
+            if ext == "rs" && open_files {
+                if let Some(mut source) = parse_source(&f, cfg)? {
+                    // there's no module tree to go by, so the path in the
+                    // directory stands in for the module
+                    source.module = f
+                        .strip_prefix(root_dir)
+                        .unwrap_or(&f)
+                        .with_extension("")
+                        .iter()
+                        .map(|part| part.to_string_lossy().to_string())
+                        .collect();
+                    parsed.push(source);
                 }
-                total
             }
-        },
-        _ => {
-            // i don't think other types can hold unsafe code
-            // double check whatever is current with the installed version of syn
-            // https://docs.rs/syn/0.15.26/syn/enum.Item.html
-            0
         }
-    };
-    result
+    }
+    Ok(unsafe_code_of(&parsed, &declared_in(&parsed)))
 }
 
-fn unsafe_lines_of_file(file: &syn::File) -> usize {
-    let mut unsafes = 0;
-    for i in &file.items {
-        unsafes += unsafe_things_of_item(&i, false);
+/// an unsafe fn can be declared in one file and called from another, so
+/// everything that's declared has to be known before classifying any calls
+fn declared_in<'a, I: IntoIterator<Item = &'a SourceFile>>(files: I) -> Declared {
+    let mut declared = Declared::new();
+    for f in files {
+        declared.collect(&f.ast);
     }
-    unsafes
+    declared
 }
 
-fn count_of_unsafe(root_dir: &Path, open_files: bool) -> Result<usize> {
-    let mut unsafe_total = 0;
-    for f in files_of(root_dir)? {
-        if let Some(ext) = f.extension() {
-            if ext == "rs" {
-                if open_files {
-                    //let mut file = File::open(dbg!(f))?;
-                    let mut file = File::open(&f)?;
-                    let mut content = String::new();
-                    file.read_to_string(&mut content)?;
-                    match syn::parse_file(&content) {
-                        Ok(ast) => {
-                            let unsafe_lines = unsafe_lines_of_file(&ast);
-                            unsafe_total += unsafe_lines;
-                        }
-                        Err(_err) => {
-                            eprintln!("error parsing {:?}", f);
-                        }
-                    }
-                }
-            }
-        }
+fn unsafe_code_of(files: &[SourceFile], declared: &Declared) -> UnsafeCode {
+    let mut code = UnsafeCode::default();
+    for f in files {
+        let sites = unsafe_sites_of_file(&f.ast, &f.module, declared);
+        code.add_sites(&f.path, sites, &f.content);
+        code.add_ffi_sites(&f.path, ffi_sites_of_file(&f.ast), &f.content);
     }
-    // start with just counting files
-    Ok(unsafe_total)
+    code
 }
 
 fn files_of(dir: &std::path::Path) -> Result<Vec<std::path::PathBuf>> {
@@ -844,38 +976,57 @@ fn files_of(dir: &std::path::Path) -> Result<Vec<std::path::PathBuf>> {
     Ok(files)
 }
 
-pub fn resolve_roots(config: &Config) -> CargoResult<Package> {
-    let root_manifest = find_root_manifest_for_wd(config.cwd())?;
-    let workspace = Workspace::new(&root_manifest, config)?;
-
-    Ok(workspace.current()?.clone())
+/// which workspace members to start from
+pub struct MemberSelection {
+    pub workspace: bool,
+    pub packages: Vec<String>,
+    pub exclude: Vec<String>,
 }
 
-pub fn resolve_packages(config: &Config, root_package: Package) -> CargoResult<Vec<Package>> {
-    let root_manifest = find_root_manifest_for_wd(config.cwd())?;
-    let workspace = Workspace::new(&root_manifest, config)?;
-
-    let (packages, resolve) = ops::resolve_ws(&workspace)?;
-
-    let mut result = HashSet::new();
-    let id = root_package.package_id();
-    let mut to_check: Vec<&PackageId> = vec![&id];
-    while let Some(id) = to_check.pop() {
-        if let Ok(package) = packages.get_one(id) {
-            if result.insert(package) {
-                let deps = resolve.deps_not_replaced(id);
-                for dep_id in deps {
-                    let dep = package.dependencies().iter()
-                        .find(|d| d.matches_id(dep_id))
-                        .unwrap_or_else(|| panic!("Looking up a packages dependency in the package failed, failed to find '{}' in '{}'", dep_id, id));
-                    if let Kind::Normal = dep.kind() {
-                        let dep_id = resolve.replacement(dep_id).unwrap_or(dep_id);
-                        to_check.push(dep_id);
-                    }
-                }
-            }
+/// the workspace members picked by `selection`. without `--workspace` or `-p`
+/// that's the package we're in, or the default members at the root of a
+/// virtual workspace
+pub fn resolve_roots(
+    workspace: &Workspace,
+    selection: &MemberSelection,
+) -> CargoResult<Vec<Package>> {
+    for name in selection.packages.iter().chain(&selection.exclude) {
+        if !workspace
+            .members()
+            .any(|member| member.name().as_str() == name.as_str())
+        {
+            failure::bail!("{} isn't a member of the workspace", name);
         }
     }
-
-    Ok(result.into_iter().cloned().collect())
+    let roots: Vec<Package> = if !selection.packages.is_empty() {
+        workspace
+            .members()
+            .filter(|member| {
+                selection
+                    .packages
+                    .iter()
+                    .any(|name| member.name().as_str() == name.as_str())
+            })
+            .cloned()
+            .collect()
+    } else if selection.workspace {
+        workspace.members().cloned().collect()
+    } else if let Some(current) = workspace.current_opt() {
+        vec![current.clone()]
+    } else {
+        workspace.default_members().cloned().collect()
+    };
+    let roots: Vec<Package> = roots
+        .into_iter()
+        .filter(|root| {
+            !selection
+                .exclude
+                .iter()
+                .any(|name| root.name().as_str() == name.as_str())
+        })
+        .collect();
+    if roots.is_empty() {
+        failure::bail!("no workspace members left to analyze");
+    }
+    Ok(roots)
 }
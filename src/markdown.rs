@@ -0,0 +1,230 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use crate::baseline::Baseline;
+use crate::code_line::{CodeLine, UnsafeCode};
+use crate::UnsafeLines;
+
+/// comfortably under the 65536 characters a review comment can hold
+const MAX_LENGTH: usize = 60_000;
+
+/// how many sites to list for a crate before leaving the rest out
+const MAX_SITES: usize = 10;
+
+/// a crate in the table, with its sites for the details
+struct Row<'a> {
+    name: String,
+    version: String,
+    /// file paths are shown relative to this
+    root: PathBuf,
+    code: Vec<&'a UnsafeCode>,
+}
+
+impl<'a> Row<'a> {
+    /// e.g. `itoa 0.4.3`, or just the path of a directory
+    fn title(&self) -> String {
+        format!("{} {}", self.name, self.version)
+            .trim_end()
+            .to_string()
+    }
+
+    fn lines(&self) -> usize {
+        self.code.iter().map(|code| code.lines.len()).sum()
+    }
+
+    /// e.g. `deref 3, call 12`
+    fn categories(&self) -> String {
+        let mut counts = BTreeMap::new();
+        for code in &self.code {
+            for (category, count) in code.category_counts() {
+                *counts.entry(category).or_insert(0) += count;
+            }
+        }
+        let counts: Vec<String> = counts
+            .iter()
+            .map(|(category, count)| format!("{} {}", category.name(), count))
+            .collect();
+        counts.join(", ")
+    }
+
+    /// the declarations and operations, labelled like `-v` does
+    fn sites(&self) -> Vec<(String, &CodeLine)> {
+        self.code
+            .iter()
+            .flat_map(|code| code.sites())
+            .filter(|(label, _)| label != "unsafe line")
+            .collect()
+    }
+}
+
+/// inline code that survives backticks in the code itself
+fn code_span(text: &str) -> String {
+    if text.contains('`') {
+        format!("`` {} ``", text)
+    } else {
+        format!("`{}`", text)
+    }
+}
+
+/// e.g. `+3`, `-1`, `0 (was 1.0.1)` or `new`
+fn change(baseline: &Baseline, row: &Row) -> String {
+    let old = match baseline.package(&row.name, &row.version) {
+        Some(old) => old,
+        None => return "new".to_string(),
+    };
+    let change = match row.lines() as i64 - old.unsafe_lines as i64 {
+        n if n > 0 => format!("+{}", n),
+        n => n.to_string(),
+    };
+    if old.version == row.version {
+        change
+    } else {
+        format!("{} (was {})", change, old.version)
+    }
+}
+
+fn render(rows: &[Row], baseline: Option<&Baseline>) -> String {
+    let with_unsafe = rows
+        .iter()
+        .filter(|row| row.lines() > 0 || !row.sites().is_empty())
+        .count();
+    let total: usize = rows.iter().map(Row::lines).sum();
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "### cargo-danger: {} of {} crate{} with unsafe code, {} unsafe line{}\n",
+        with_unsafe,
+        rows.len(),
+        if rows.len() == 1 { "" } else { "s" },
+        total,
+        if total == 1 { "" } else { "s" }
+    );
+
+    // crates without unsafe code only make the table when that changed
+    let shown: Vec<&Row> = rows
+        .iter()
+        .filter(|row| {
+            row.lines() > 0
+                || !row.sites().is_empty()
+                || baseline.is_some_and(|baseline| change(baseline, row) != "0")
+        })
+        .collect();
+    if !shown.is_empty() {
+        match baseline {
+            Some(_) => {
+                out.push_str("| crate | version | unsafe | change | categories |\n");
+                out.push_str("|---|---|--:|--:|---|\n");
+            }
+            None => {
+                out.push_str("| crate | version | unsafe | categories |\n");
+                out.push_str("|---|---|--:|---|\n");
+            }
+        }
+    }
+    for row in &shown {
+        let change = match baseline {
+            Some(baseline) => format!(" {} |", change(baseline, row)),
+            None => String::new(),
+        };
+        let _ = writeln!(
+            out,
+            "| {} | {} | {} |{} {} |",
+            row.name,
+            row.version,
+            row.lines(),
+            change,
+            row.categories()
+        );
+    }
+    let hidden = rows.len() - shown.len();
+    if hidden > 0 {
+        let _ = writeln!(
+            out,
+            "\n{} more crate{} without unsafe code.",
+            hidden,
+            if hidden == 1 { "" } else { "s" }
+        );
+    }
+    if let Some(baseline) = baseline {
+        let present: Vec<(String, String)> = rows
+            .iter()
+            .map(|row| (row.name.clone(), row.version.clone()))
+            .collect();
+        let gone: Vec<String> = baseline
+            .gone(&present)
+            .iter()
+            .map(|p| format!("{} {} ({} unsafe)", p.name, p.version, p.unsafe_lines))
+            .collect();
+        if !gone.is_empty() {
+            let _ = writeln!(out, "\nGone since the baseline: {}.", gone.join(", "));
+        }
+    }
+
+    for row in shown {
+        let sites = row.sites();
+        if sites.is_empty() {
+            continue;
+        }
+        let mut details = String::new();
+        let _ = writeln!(
+            details,
+            "\n<details><summary>{}: {} unsafe line{}</summary>\n",
+            row.title(),
+            row.lines(),
+            if row.lines() == 1 { "" } else { "s" }
+        );
+        for (label, code) in sites.iter().take(MAX_SITES) {
+            let path = code
+                .file_path
+                .strip_prefix(&row.root)
+                .unwrap_or(&code.file_path);
+            let location = format!("{}:{}:{}", path.display(), code.line_number, code.column);
+            let _ = writeln!(
+                details,
+                "- {} {}: {}",
+                code_span(&location),
+                label,
+                code_span(&code.snippet())
+            );
+        }
+        if sites.len() > MAX_SITES {
+            let _ = writeln!(details, "- and {} more", sites.len() - MAX_SITES);
+        }
+        details.push_str("\n</details>\n");
+        if out.len() + details.len() > MAX_LENGTH {
+            out.push_str("\nThe rest of the details were left out to fit in a comment.\n");
+            break;
+        }
+        out.push_str(&details);
+    }
+    out
+}
+
+/// one row per crate, counting the targets that get built, or all of them
+pub fn render_packages(
+    packages: &[UnsafeLines],
+    all_targets: bool,
+    baseline: Option<&Baseline>,
+) -> String {
+    let rows: Vec<Row> = packages
+        .iter()
+        .map(|p| Row {
+            name: p.package.name().to_string(),
+            version: p.package.version().to_string(),
+            root: p.package.root().to_path_buf(),
+            code: p.built_targets(all_targets).map(|(_, code)| code).collect(),
+        })
+        .collect();
+    render(&rows, baseline)
+}
+
+pub fn render_directory(path: &Path, code: &UnsafeCode, baseline: Option<&Baseline>) -> String {
+    let rows = vec![Row {
+        name: path.display().to_string(),
+        version: String::new(),
+        root: path.to_path_buf(),
+        code: vec![code],
+    }];
+    render(&rows, baseline)
+}
@@ -0,0 +1,153 @@
+use syn::{Attribute, Item, Lit, Meta};
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::cfg::CfgSet;
+use crate::Result;
+
+/// a parsed rust source file
+pub struct SourceFile {
+    pub path: PathBuf,
+    /// the path of the module it holds within its crate, e.g. `["fmt", "udiv128"]`,
+    /// empty for the crate root
+    pub module: Vec<String>,
+    pub content: String,
+    pub ast: syn::File,
+}
+
+/// read and parse `path`, leaving out whatever `cfg` compiles away. complains
+/// on stderr and gives back `None` when it isn't valid rust
+pub fn parse_source(path: &Path, cfg: &CfgSet) -> Result<Option<SourceFile>> {
+    let mut file = File::open(path)?;
+    let mut content = String::new();
+    file.read_to_string(&mut content)?;
+    match syn::parse_file(&content) {
+        Ok(mut ast) => {
+            cfg.prune(&mut ast);
+            Ok(Some(SourceFile {
+                path: path.to_path_buf(),
+                module: vec![],
+                content,
+                ast,
+            }))
+        }
+        Err(_err) => {
+            eprintln!("error parsing {:?}", path);
+            Ok(None)
+        }
+    }
+}
+
+/// the value of a `#[path = "..."]` attribute
+fn path_attr(attrs: &[Attribute]) -> Option<String> {
+    for attr in attrs {
+        if let Some(Meta::NameValue(name_value)) = attr.interpret_meta() {
+            if name_value.ident == "path" {
+                if let Lit::Str(path) = &name_value.lit {
+                    return Some(path.value());
+                }
+            }
+        }
+    }
+    None
+}
+
+struct ModuleTree<'a> {
+    cfg: &'a CfgSet,
+    seen: &'a mut HashSet<PathBuf>,
+    files: Vec<SourceFile>,
+}
+
+impl<'a> ModuleTree<'a> {
+    /// parse `path` and everything it declares with `mod foo;`, where `dir` is
+    /// the directory its child modules live in and `module` the module it holds
+    fn follow(&mut self, path: &Path, dir: &Path, module: Vec<String>) -> Result<()> {
+        if !path.is_file() {
+            eprintln!("couldn't find module file {:?}", path);
+            return Ok(());
+        }
+        // #[path] can wander off with `..`, so compare files by where they
+        // really are
+        let path = &path.canonicalize()?;
+        if !self.seen.insert(path.to_path_buf()) {
+            return Ok(());
+        }
+        if let Some(mut source) = parse_source(path, self.cfg)? {
+            let file_dir = path.parent().unwrap_or_else(|| Path::new(""));
+            // keep parents ahead of their children
+            let index = self.files.len();
+            self.follow_items(&source.ast.items, dir, file_dir, false, &module)?;
+            source.module = module;
+            self.files.insert(index, source);
+        }
+        Ok(())
+    }
+
+    /// `dir` is where child modules of these items live, `file_dir` the
+    /// directory of the file they're written in, which `#[path]` is relative to
+    /// outside of inline modules, and `module` the module they're in
+    fn follow_items(
+        &mut self,
+        items: &[Item],
+        dir: &Path,
+        file_dir: &Path,
+        in_inline_mod: bool,
+        module: &[String],
+    ) -> Result<()> {
+        for item in items {
+            let mod_def = match item {
+                Item::Mod(mod_def) => mod_def,
+                _ => continue,
+            };
+            let name = mod_def.ident.to_string();
+            let path = path_attr(&mod_def.attrs);
+            let mut module = module.to_vec();
+            module.push(name.clone());
+            if let Some((_, items)) = &mod_def.content {
+                // `mod foo { mod bar; }` looks for bar in foo/
+                let dir = dir.join(path.unwrap_or(name));
+                self.follow_items(items, &dir, file_dir, true, &module)?;
+                continue;
+            }
+            match path {
+                Some(path) => {
+                    // a module loaded through #[path] acts like a mod.rs
+                    let base = if in_inline_mod { dir } else { file_dir };
+                    let path = base.join(path);
+                    let child_dir = path.parent().unwrap_or(base).to_path_buf();
+                    self.follow(&path, &child_dir, module)?;
+                }
+                None => {
+                    // foo.rs keeps its own children in foo/, just like foo/mod.rs
+                    let flat = dir.join(format!("{}.rs", name));
+                    let nested = dir.join(&name).join("mod.rs");
+                    let path = if flat.is_file() { flat } else { nested };
+                    self.follow(&path, &dir.join(&name), module)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// every file that's part of the crate rooted at `root`, like `src/lib.rs`,
+/// found by following the `mod` declarations that `cfg` keeps. files already in
+/// `seen` are skipped, so crates that share modules only get them counted once.
+/// paths in `seen` are canonicalized.
+pub fn crate_files(
+    root: &Path,
+    cfg: &CfgSet,
+    seen: &mut HashSet<PathBuf>,
+) -> Result<Vec<SourceFile>> {
+    let mut tree = ModuleTree {
+        cfg,
+        seen,
+        files: vec![],
+    };
+    let dir = root.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+    tree.follow(root, &dir, vec![])?;
+    Ok(tree.files)
+}
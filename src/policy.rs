@@ -0,0 +1,168 @@
+use serde_derive::Deserialize;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::baseline::Baseline;
+use crate::json;
+use crate::walker::Category;
+use crate::{Result, UnsafeLines};
+
+/// what a budget can limit, besides the operation categories
+const TOTALS: &[&str] = &["unsafe_lines", "unsafe_declarations"];
+
+/// which crates may have unsafe code in them, who approved it and why. a
+/// crate that isn't listed may not have any
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    #[serde(default)]
+    crates: BTreeMap<String, Approval>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Approval {
+    reviewer: String,
+    justification: String,
+    /// the most of each kind of unsafe code the crate may have, out of
+    /// `TOTALS` and the operation categories. anything left out is unlimited
+    #[serde(default)]
+    budget: BTreeMap<String, usize>,
+}
+
+impl Policy {
+    /// `danger.toml` next to the workspace's root manifest, or failing that
+    /// `[workspace.metadata.danger]` in the manifest itself. none when there's
+    /// neither
+    pub fn find(root: &Path) -> Result<Option<Policy>> {
+        let path = root.join("danger.toml");
+        let policy: Policy = if path.is_file() {
+            let text = fs::read_to_string(&path)?;
+            toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?
+        } else {
+            let path = root.join("Cargo.toml");
+            let manifest: toml::Value = toml::from_str(&fs::read_to_string(&path)?)?;
+            let metadata = manifest
+                .get("workspace")
+                .and_then(|workspace| workspace.get("metadata"))
+                .and_then(|metadata| metadata.get("danger"));
+            match metadata {
+                Some(metadata) => metadata.clone().try_into().map_err(|e| {
+                    format!("[workspace.metadata.danger] in {}: {}", path.display(), e)
+                })?,
+                None => return Ok(None),
+            }
+        };
+        policy.validate()?;
+        Ok(Some(policy))
+    }
+
+    fn validate(&self) -> Result<()> {
+        for (name, approval) in &self.crates {
+            for key in approval.budget.keys() {
+                let is_category = Category::ALL.iter().any(|category| category.name() == key);
+                if !TOTALS.contains(&key.as_str()) && !is_category {
+                    return Err(format!(
+                        "the budget of {} has {}, which isn't one of {}",
+                        name,
+                        key,
+                        TOTALS
+                            .iter()
+                            .cloned()
+                            .chain(Category::ALL.iter().map(|category| category.name()))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )
+                    .into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// prints every package with unsafe code the policy doesn't allow, in the
+    /// targets that get built or all of them. true when there's none
+    pub fn check(&self, packages: &[UnsafeLines], all_targets: bool) -> bool {
+        let mut allowed = true;
+        for p in packages {
+            let counts = p.counts(all_targets);
+            let title = format!("{} v{}", p.package.name(), p.package.version());
+            match self.crates.get(p.package.name().as_str()) {
+                None => {
+                    let found: Vec<String> = counts
+                        .iter()
+                        .filter(|(_, count)| **count > 0)
+                        .map(|(name, count)| format!("{} {}", name, count))
+                        .collect();
+                    if !found.is_empty() {
+                        allowed = false;
+                        println!(
+                            "{} isn't allowed any unsafe code: {}",
+                            title,
+                            found.join(", ")
+                        );
+                    }
+                }
+                Some(approval) => {
+                    let over: Vec<String> = approval
+                        .budget
+                        .iter()
+                        .filter_map(|(name, limit)| {
+                            let count = counts.get(name.as_str()).cloned().unwrap_or(0);
+                            if count > *limit {
+                                Some(format!("{} {} > {}", name, count, limit))
+                            } else {
+                                None
+                            }
+                        })
+                        .collect();
+                    if !over.is_empty() {
+                        allowed = false;
+                        println!("{} is over its budget: {}", title, over.join(", "));
+                        println!(
+                            "  approved by {}: {}",
+                            approval.reviewer, approval.justification
+                        );
+                    }
+                }
+            }
+        }
+        if allowed {
+            println!("every package keeps to the policy");
+        }
+        allowed
+    }
+}
+
+/// fails when a package breaks the policy at the workspace's `root`, or there's
+/// more unsafe code than in `baseline`, in the targets that get built or all
+/// of them. there has to be one or the other to check against
+pub fn check_packages(
+    root: &Path,
+    packages: &[UnsafeLines],
+    all_targets: bool,
+    baseline: Option<Baseline>,
+) -> Result<()> {
+    let policy = Policy::find(root)?;
+    if policy.is_none() && baseline.is_none() {
+        return Err("check needs --baseline FILE or a danger.toml policy".into());
+    }
+    let mut failures = vec![];
+    if let Some(policy) = policy {
+        if !policy.check(packages, all_targets) {
+            failures.push("unsafe code broke the policy");
+        }
+    }
+    if let Some(baseline) = baseline {
+        let current = Baseline::parse(&json::render_packages(packages, all_targets)?)?;
+        if !baseline.check(&current) {
+            failures.push("unsafe code grew since the baseline");
+        }
+    }
+    if !failures.is_empty() {
+        return Err(failures.join(", and ").into());
+    }
+    Ok(())
+}
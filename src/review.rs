@@ -0,0 +1,220 @@
+use serde_derive::{Deserialize, Serialize};
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use crate::code_line::CodeLine;
+use crate::{Result, UnsafeLines};
+
+/// where the ledger lives unless `--ledger` says otherwise, next to the
+/// workspace's root manifest
+pub const LEDGER_FILE: &str = "danger-reviews.toml";
+
+/// the unsafe sites someone has looked over, by fingerprint, so that they
+/// stay reviewed when only the lines around them move
+#[derive(Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Ledger {
+    #[serde(default, rename = "review")]
+    reviews: Vec<Review>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Review {
+    #[serde(rename = "crate")]
+    krate: String,
+    /// the version that was reviewed, for whoever reads the ledger
+    version: String,
+    /// e.g. `deref`, along with the path of the item it's in, so that a site
+    /// that changed can be told from a new one
+    site: String,
+    item: String,
+    fingerprint: String,
+    reviewer: String,
+    note: String,
+}
+
+/// e.g. `deref in Buffer::format`
+fn describe(label: &str, code: &CodeLine) -> String {
+    match &code.fingerprint {
+        Some(fingerprint) if !fingerprint.item.is_empty() => {
+            format!("{} in {}", label, fingerprint.item)
+        }
+        _ => label.to_string(),
+    }
+}
+
+impl Ledger {
+    /// an empty ledger when there's no file yet
+    pub fn load(path: &Path) -> Result<Ledger> {
+        if !path.is_file() {
+            return Ok(Ledger::default());
+        }
+        let text = fs::read_to_string(path)?;
+        Ok(toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    fn review_of(&self, krate: &str, code: &CodeLine) -> Option<&Review> {
+        let fingerprint = code.fingerprint.as_ref()?;
+        self.reviews
+            .iter()
+            .find(|review| review.krate == krate && review.fingerprint == fingerprint.hash)
+    }
+
+    pub fn is_reviewed(&self, krate: &str, code: &CodeLine) -> bool {
+        self.review_of(krate, code).is_some()
+    }
+
+    /// the latest review of the same kind of site in the same item, when the
+    /// site itself hasn't been reviewed
+    fn changed_since(&self, krate: &str, label: &str, code: &CodeLine) -> Option<&Review> {
+        let fingerprint = code.fingerprint.as_ref()?;
+        if self.is_reviewed(krate, code) {
+            return None;
+        }
+        self.reviews.iter().rev().find(|review| {
+            review.krate == krate && review.site == label && review.item == fingerprint.item
+        })
+    }
+
+    /// the sites of `packages` that haven't been reviewed, in the targets that
+    /// get built or all of them
+    fn pending<'a>(
+        &self,
+        packages: &'a [UnsafeLines],
+        all_targets: bool,
+    ) -> Vec<(&'a UnsafeLines, String, &'a CodeLine)> {
+        let mut pending = vec![];
+        for p in packages {
+            for (_, code) in p.built_targets(all_targets) {
+                for (label, code) in code.sites() {
+                    if code.fingerprint.is_some() && !self.is_reviewed(&p.package.name(), code) {
+                        pending.push((p, label, code));
+                    }
+                }
+            }
+        }
+        pending
+    }
+
+    /// prints every site that nobody has reviewed, or that changed since it
+    /// was, with the fingerprint to review it by
+    pub fn print_pending(&self, packages: &[UnsafeLines], all_targets: bool) {
+        let pending = self.pending(packages, all_targets);
+        let mut last_title = String::new();
+        for (p, label, code) in &pending {
+            let title = format!("{} v{}", p.package.name(), p.package.version());
+            if title != last_title {
+                println!("{}", title);
+                last_title = title;
+            }
+            let changed = match self.changed_since(&p.package.name(), label, code) {
+                Some(review) => format!(
+                    ", changed since {} reviewed v{}",
+                    review.reviewer, review.version
+                ),
+                None => String::new(),
+            };
+            println!(
+                "  {}:{}:{} {} [{}]{}",
+                code.file_path
+                    .strip_prefix(p.package.root())
+                    .unwrap_or(&code.file_path)
+                    .display(),
+                code.line_number,
+                code.column,
+                describe(label, code),
+                code.fingerprint.as_ref().unwrap().hash,
+                changed
+            );
+            println!("    {}", code.snippet());
+        }
+        if pending.is_empty() {
+            println!("every unsafe site has been reviewed");
+        } else {
+            println!(
+                "{} unsafe site{} to review",
+                pending.len(),
+                if pending.len() == 1 { "" } else { "s" }
+            );
+        }
+    }
+
+    /// records the unreviewed sites with one of `fingerprints` as reviewed,
+    /// or every one of them when there are none. a site that shows up more
+    /// than once, like the same code in two targets, is recorded once. gives
+    /// back how many sites were recorded
+    pub fn record(
+        &mut self,
+        packages: &[UnsafeLines],
+        all_targets: bool,
+        fingerprints: &[&str],
+        reviewer: &str,
+        note: &str,
+    ) -> Result<usize> {
+        let pending = self.pending(packages, all_targets);
+        for fingerprint in fingerprints {
+            let found = pending
+                .iter()
+                .any(|(_, _, code)| code.fingerprint.as_ref().unwrap().hash == *fingerprint);
+            if !found {
+                return Err(
+                    format!("no unreviewed unsafe site has fingerprint {}", fingerprint).into(),
+                );
+            }
+        }
+        let mut recorded = HashSet::new();
+        let mut reviews = vec![];
+        for (p, label, code) in pending {
+            let fingerprint = code.fingerprint.as_ref().unwrap();
+            if !fingerprints.is_empty() && !fingerprints.contains(&fingerprint.hash.as_str()) {
+                continue;
+            }
+            let krate = p.package.name().to_string();
+            if !recorded.insert((krate.clone(), fingerprint.hash.clone())) {
+                continue;
+            }
+            reviews.push(Review {
+                krate,
+                version: p.package.version().to_string(),
+                site: label,
+                item: fingerprint.item.clone(),
+                fingerprint: fingerprint.hash.clone(),
+                reviewer: reviewer.to_string(),
+                note: note.to_string(),
+            });
+        }
+        let count = reviews.len();
+        self.reviews.extend(reviews);
+        Ok(count)
+    }
+}
+
+/// records the sites with one of `fingerprints`, or every one left when there
+/// are none, as `Ledger::record` does, and saves the ledger at `path`
+pub fn record_reviews(
+    path: &Path,
+    packages: &[UnsafeLines],
+    all_targets: bool,
+    fingerprints: &[&str],
+    reviewer: &str,
+    note: &str,
+) -> Result<()> {
+    let mut ledger = Ledger::load(path)?;
+    let recorded = ledger.record(packages, all_targets, fingerprints, reviewer, note)?;
+    ledger.save(path)?;
+    println!(
+        "recorded {} reviewed site{} in {}",
+        recorded,
+        if recorded == 1 { "" } else { "s" },
+        path.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,299 @@
+use serde_derive::Serialize;
+use url::percent_encoding::{utf8_percent_encode, PATH_SEGMENT_ENCODE_SET};
+use url::Url;
+
+use std::collections::BTreeMap;
+use std::path::{Component, Path};
+
+use crate::code_line::{CodeLine, UnsafeCode};
+use crate::walker::{Category, DeclarationKind};
+use crate::{Result, UnsafeLines};
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const SARIF_VERSION: &str = "2.1.0";
+
+/// where results under the directory cargo danger ran from are relative to, so
+/// a viewer can map them onto its own checkout
+const SRCROOT: &str = "%SRCROOT%";
+
+/// the rules besides the operation ones: the id, what it flags, and its level
+const OTHER_RULES: &[(&str, &str, &str)] = &[
+    ("unsafe_fn", "unsafe fn declaration", "note"),
+    ("unsafe_trait", "unsafe trait declaration", "note"),
+    ("unsafe_line", "line of code in an unsafe block", "note"),
+];
+
+/// what the rule for an operation category flags
+fn describe(category: Category) -> &'static str {
+    match category {
+        Category::RawPointerDeref => "dereference of a raw pointer",
+        Category::UnsafeCall => "call to an unsafe fn or a foreign function",
+        Category::StaticMut => "use of a static mut or a foreign static",
+        Category::UnionField => "read of a union field",
+        Category::InlineAsm => "inline assembly",
+        Category::UnsafeTraitImpl => "unsafe impl of an unsafe trait",
+    }
+}
+
+/// every rule a result can have, an operation rule named after
+/// `Category::name` for each category and then `OTHER_RULES`
+fn rules() -> Vec<(&'static str, &'static str, &'static str)> {
+    Category::ALL
+        .iter()
+        .map(|&category| (category.name(), describe(category), "warning"))
+        .chain(OTHER_RULES.iter().cloned())
+        .collect()
+}
+
+#[derive(Serialize)]
+struct Log {
+    #[serde(rename = "$schema")]
+    schema: &'static str,
+    version: &'static str,
+    runs: Vec<Run>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Run {
+    tool: Tool,
+    original_uri_base_ids: BTreeMap<&'static str, ArtifactLocation>,
+    /// proc_macro2 counts columns in chars, not the default UTF-16 code units
+    column_kind: &'static str,
+    results: Vec<SarifResult>,
+}
+
+#[derive(Serialize)]
+struct Tool {
+    driver: Driver,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Driver {
+    name: &'static str,
+    version: &'static str,
+    information_uri: &'static str,
+    rules: Vec<Rule>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Rule {
+    id: &'static str,
+    short_description: Message,
+    default_configuration: Configuration,
+}
+
+#[derive(Serialize)]
+struct Configuration {
+    level: &'static str,
+}
+
+#[derive(Serialize)]
+struct Message {
+    text: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifResult {
+    rule_id: &'static str,
+    rule_index: usize,
+    level: &'static str,
+    message: Message,
+    locations: Vec<Location>,
+    /// lets code scanning keep track of a result as the lines around it move
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    partial_fingerprints: BTreeMap<&'static str, String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Location {
+    physical_location: PhysicalLocation,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PhysicalLocation {
+    artifact_location: ArtifactLocation,
+    region: Region,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ArtifactLocation {
+    uri: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uri_base_id: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Region {
+    start_line: usize,
+    start_column: usize,
+    end_line: usize,
+    end_column: usize,
+    snippet: Message,
+}
+
+/// turns unsafe sites into results, with files under `root` made relative to
+/// it
+struct Results<'a> {
+    root: &'a Path,
+    results: Vec<SarifResult>,
+}
+
+impl<'a> Results<'a> {
+    /// `context` says where the site was found, e.g. `mid v0.1.0 (lib)`
+    fn add(&mut self, rule_id: &'static str, code: &CodeLine, context: &str) {
+        let rules = rules();
+        let rule_index = rules.iter().position(|(id, _, _)| *id == rule_id).unwrap();
+        let (_, description, level) = rules[rule_index];
+        let text = if context.is_empty() {
+            description.to_string()
+        } else {
+            format!("{} in {}", description, context)
+        };
+        let artifact_location = self.artifact_location(&code.file_path);
+        let partial_fingerprints = code
+            .fingerprint
+            .iter()
+            .map(|fingerprint| ("cargoDanger/v1", fingerprint.hash.clone()))
+            .collect();
+        self.results.push(SarifResult {
+            rule_id,
+            rule_index,
+            level,
+            message: Message { text },
+            locations: vec![Location {
+                physical_location: PhysicalLocation {
+                    artifact_location,
+                    region: Region {
+                        start_line: code.line_number,
+                        start_column: code.column,
+                        end_line: code.end_line_number,
+                        end_column: code.end_column,
+                        snippet: Message {
+                            text: code.snippet(),
+                        },
+                    },
+                },
+            }],
+            partial_fingerprints,
+        });
+    }
+
+    /// an `unsafe impl` is reported once, as the operation
+    fn add_code(&mut self, code: &UnsafeCode, context: &str) {
+        for declaration in &code.declarations {
+            match declaration.kind {
+                DeclarationKind::Fn => self.add("unsafe_fn", &declaration.code, context),
+                DeclarationKind::Trait => self.add("unsafe_trait", &declaration.code, context),
+                DeclarationKind::Impl => {}
+            }
+        }
+        for operation in &code.operations {
+            self.add(operation.category.name(), &operation.code, context);
+        }
+        for line in &code.lines {
+            self.add("unsafe_line", line, context);
+        }
+    }
+
+    /// relative to `%SRCROOT%` when the file is under the root, like the
+    /// workspace's own crates, and an absolute file url when it isn't, like
+    /// anything from the registry
+    fn artifact_location(&self, path: &Path) -> ArtifactLocation {
+        if let Ok(relative) = path.strip_prefix(self.root) {
+            let segments: Vec<String> = relative
+                .components()
+                .filter_map(|component| match component {
+                    Component::Normal(segment) => Some(
+                        utf8_percent_encode(&segment.to_string_lossy(), PATH_SEGMENT_ENCODE_SET)
+                            .to_string(),
+                    ),
+                    _ => None,
+                })
+                .collect();
+            return ArtifactLocation {
+                uri: segments.join("/"),
+                uri_base_id: Some(SRCROOT),
+            };
+        }
+        ArtifactLocation {
+            uri: Url::from_file_path(path)
+                .map(|url| url.to_string())
+                .unwrap_or_else(|_| path.display().to_string()),
+            uri_base_id: None,
+        }
+    }
+
+    fn render(self) -> Result<String> {
+        let mut original_uri_base_ids = BTreeMap::new();
+        if let Ok(url) = Url::from_directory_path(self.root) {
+            original_uri_base_ids.insert(
+                SRCROOT,
+                ArtifactLocation {
+                    uri: url.to_string(),
+                    uri_base_id: None,
+                },
+            );
+        }
+        let rules = rules()
+            .into_iter()
+            .map(|(id, description, level)| Rule {
+                id,
+                short_description: Message {
+                    text: description.to_string(),
+                },
+                default_configuration: Configuration { level },
+            })
+            .collect();
+        let log = Log {
+            schema: SARIF_SCHEMA,
+            version: SARIF_VERSION,
+            runs: vec![Run {
+                tool: Tool {
+                    driver: Driver {
+                        name: env!("CARGO_PKG_NAME"),
+                        version: env!("CARGO_PKG_VERSION"),
+                        information_uri: env!("CARGO_PKG_REPOSITORY"),
+                        rules,
+                    },
+                },
+                original_uri_base_ids,
+                column_kind: "unicodeCodePoints",
+                results: self.results,
+            }],
+        };
+        Ok(serde_json::to_string_pretty(&log)?)
+    }
+}
+
+/// one result per unsafe site in the targets that get built, or all of them
+pub fn render_packages(root: &Path, packages: &[UnsafeLines], all_targets: bool) -> Result<String> {
+    let mut results = Results {
+        root,
+        results: vec![],
+    };
+    for p in packages {
+        let id = p.package.package_id();
+        for (kind, code) in p.built_targets(all_targets) {
+            let context = format!("{} v{} ({})", id.name(), id.version(), kind.name());
+            results.add_code(code, &context);
+        }
+    }
+    results.render()
+}
+
+pub fn render_directory(root: &Path, code: &UnsafeCode) -> Result<String> {
+    let mut results = Results {
+        root,
+        results: vec![],
+    };
+    results.add_code(code, "");
+    results.render()
+}
@@ -0,0 +1,80 @@
+use cargo::core::manifest::{LibKind, TargetKind as CargoTargetKind};
+use cargo::core::Target;
+
+use std::collections::BTreeMap;
+
+/// the kinds of target a package can have, which matter differently: libs and
+/// bins end up in our binaries, build scripts and proc-macros run on the
+/// machine doing the build, and tests, examples and benches never leave the
+/// package they're in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TargetKind {
+    Lib,
+    ProcMacro,
+    Bin,
+    BuildScript,
+    Test,
+    Example,
+    Bench,
+}
+
+impl TargetKind {
+    pub fn of(target: &Target) -> TargetKind {
+        match target.kind() {
+            CargoTargetKind::Lib(kinds) if kinds.contains(&LibKind::ProcMacro) => {
+                TargetKind::ProcMacro
+            }
+            CargoTargetKind::Lib(_) => TargetKind::Lib,
+            CargoTargetKind::Bin => TargetKind::Bin,
+            CargoTargetKind::CustomBuild => TargetKind::BuildScript,
+            CargoTargetKind::Test => TargetKind::Test,
+            CargoTargetKind::ExampleLib(_) | CargoTargetKind::ExampleBin => TargetKind::Example,
+            CargoTargetKind::Bench => TargetKind::Bench,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TargetKind::Lib => "lib",
+            TargetKind::ProcMacro => "proc-macro",
+            TargetKind::Bin => "bin",
+            TargetKind::BuildScript => "build-script",
+            TargetKind::Test => "test",
+            TargetKind::Example => "example",
+            TargetKind::Bench => "bench",
+        }
+    }
+
+    /// whether this gets built by a plain `cargo build`, as opposed to only
+    /// when testing, benchmarking or running examples
+    pub fn is_built(self) -> bool {
+        match self {
+            TargetKind::Lib | TargetKind::ProcMacro | TargetKind::Bin | TargetKind::BuildScript => {
+                true
+            }
+            TargetKind::Test | TargetKind::Example | TargetKind::Bench => false,
+        }
+    }
+
+    /// whether this gets built for the machine doing the build rather than
+    /// for the target
+    pub fn runs_on_host(self) -> bool {
+        matches!(self, TargetKind::ProcMacro | TargetKind::BuildScript)
+    }
+
+    /// whether this gets built with `--test`, which turns on `cfg(test)`
+    pub fn is_test(self) -> bool {
+        matches!(self, TargetKind::Test | TargetKind::Bench)
+    }
+}
+
+/// the targets that get built by a plain `cargo build`, or all of them
+pub fn built_targets<T>(
+    targets: &BTreeMap<TargetKind, T>,
+    all_targets: bool,
+) -> impl Iterator<Item = (TargetKind, &T)> {
+    targets
+        .iter()
+        .filter(move |(kind, _)| all_targets || kind.is_built())
+        .map(|(&kind, value)| (kind, value))
+}
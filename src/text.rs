@@ -0,0 +1,167 @@
+use crate::code_line::{CodeLine, UnsafeCode};
+use crate::deps::dependency_tag;
+use crate::review::Ledger;
+use crate::UnsafeLines;
+
+/// e.g. `mid [depth 1, normal]`, along with the members that use it when
+/// there's more than one
+fn title(p: &UnsafeLines, several_members: bool) -> String {
+    format!(
+        "{}{}",
+        p.package.package_id().name(),
+        dependency_tag(
+            p.depth,
+            &p.dep_kinds,
+            if several_members { &p.members } else { &[] }
+        )
+    )
+}
+
+/// prints the counts of each target of each package that has unsafe code, in
+/// the targets that get built or all of them. `verbose` prints every site too,
+/// leaving out the ones in `ledger`
+pub fn print_packages(
+    packages: &[UnsafeLines],
+    several_members: bool,
+    all_targets: bool,
+    verbose: bool,
+    ledger: &Ledger,
+) {
+    for p in packages {
+        let title = title(p, several_members);
+        for (kind, code) in p.built_targets(all_targets) {
+            if code.is_empty() {
+                continue;
+            }
+            println!(
+                "{} ({}), {}, {}{}",
+                title,
+                kind.name(),
+                code.lines.len(),
+                code.declarations.len(),
+                categories_summary(code)
+            );
+            if verbose {
+                let name = p.package.name();
+                let reviewed = print_unsafe_code(code, |code| ledger.is_reviewed(&name, code));
+                if reviewed > 0 {
+                    println!(
+                        "  and {} reviewed site{}",
+                        reviewed,
+                        if reviewed == 1 { "" } else { "s" }
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// prints the FFI surface of each target of each package that has one
+pub fn print_packages_ffi(packages: &[UnsafeLines], several_members: bool, all_targets: bool) {
+    for p in packages {
+        let title = title(p, several_members);
+        for (kind, code) in p.built_targets(all_targets) {
+            if !code.ffi.is_empty() {
+                println!("{} ({})", title, kind.name());
+                print_ffi(code);
+            }
+        }
+    }
+}
+
+/// prints the .rs files of each package that none of its targets use
+pub fn print_orphans(packages: &[UnsafeLines], several_members: bool) {
+    for p in packages {
+        if !p.orphans.is_empty() {
+            println!("{}", title(p, several_members));
+            for orphan in &p.orphans {
+                println!("  {}", orphan.display());
+            }
+        }
+    }
+}
+
+/// prints the counts of the unsafe code found in a directory, and with
+/// `verbose` every site
+pub fn print_directory(code: &UnsafeCode, verbose: bool) {
+    println!(
+        "{} unsafe lines, {} unsafe declarations{}",
+        code.lines.len(),
+        code.declarations.len(),
+        categories_summary(code)
+    );
+    if verbose {
+        print_unsafe_code(code, |_| false);
+    }
+}
+
+/// e.g. ` (deref: 3, call: 12)`, or nothing when there are no operations
+fn categories_summary(code: &UnsafeCode) -> String {
+    let counts = code.category_counts();
+    if counts.is_empty() {
+        return String::new();
+    }
+    let counts: Vec<String> = counts
+        .iter()
+        .map(|(category, count)| format!("{}: {}", category.name(), count))
+        .collect();
+    format!(" ({})", counts.join(", "))
+}
+
+fn print_code_line(line: &CodeLine, label: &str) {
+    println!(
+        "  {}:{}:{}-{}:{}{}",
+        line.file_path.display(),
+        line.line_number,
+        line.column,
+        line.end_line_number,
+        line.end_column,
+        label
+    );
+    for raw_line in line.raw_line.lines() {
+        println!("    {}", raw_line);
+    }
+}
+
+/// leaves out the sites `reviewed` says were, giving back how many
+fn print_unsafe_code<F: Fn(&CodeLine) -> bool>(code: &UnsafeCode, reviewed: F) -> usize {
+    let mut left_out = 0;
+    let mut print = |line: &CodeLine, label: &str| {
+        if reviewed(line) {
+            left_out += 1;
+        } else {
+            print_code_line(line, label);
+        }
+    };
+    for declaration in &code.declarations {
+        let label = format!(" unsafe {} declaration", declaration.kind.name());
+        print(&declaration.code, &label);
+    }
+    for operation in &code.operations {
+        let label = format!(" {}", operation.category.name());
+        print(&operation.code, &label);
+    }
+    for line in &code.lines {
+        print(line, "");
+    }
+    left_out
+}
+
+pub fn print_ffi(code: &UnsafeCode) {
+    for ffi in &code.ffi {
+        let abi = match &ffi.abi {
+            Some(abi) => format!(" [{}]", abi),
+            None => String::new(),
+        };
+        println!(
+            "  {}{} {}: {} ({}:{}:{})",
+            ffi.kind.name(),
+            abi,
+            ffi.name,
+            ffi.code.snippet(),
+            ffi.code.file_path.display(),
+            ffi.code.line_number,
+            ffi.code.column
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use petgraph::graph::NodeIndex;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use crate::deps::{edge_tag, DepGraph, DepKind};
+
+/// prints the dependency graph like `cargo tree` does, one tree per root,
+/// with each package's own count of unsafe lines and the count of its whole
+/// subtree. a package whose subtree was already printed gets a `(*)` instead
+/// of printing it again
+pub fn print_tree(deps: &DepGraph, own: &HashMap<NodeIndex, usize>) {
+    for (i, root) in deps.roots.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        let mut tree = TreeView {
+            deps,
+            own,
+            printed: HashSet::new(),
+        };
+        tree.print(*root, &BTreeSet::new(), "", "");
+    }
+}
+
+struct TreeView<'a> {
+    deps: &'a DepGraph,
+    own: &'a HashMap<NodeIndex, usize>,
+    printed: HashSet<NodeIndex>,
+}
+
+impl<'a> TreeView<'a> {
+    fn print(
+        &mut self,
+        node: NodeIndex,
+        kinds: &BTreeSet<DepKind>,
+        prefix: &str,
+        child_prefix: &str,
+    ) {
+        let package = &self.deps.graph[node];
+        let first_visit = self.printed.insert(node);
+        println!(
+            "{}{} v{}{}: {} unsafe, {} in subtree{}",
+            prefix,
+            package.name(),
+            package.version(),
+            edge_tag(kinds),
+            self.own.get(&node).cloned().unwrap_or(0),
+            self.deps.subtree_total(node, self.own),
+            if first_visit { "" } else { " (*)" }
+        );
+        if !first_visit {
+            return;
+        }
+
+        let mut children: BTreeMap<(String, NodeIndex), BTreeSet<DepKind>> = BTreeMap::new();
+        for child in self.deps.graph.neighbors(node) {
+            let name = self.deps.graph[child].name().to_string();
+            children.insert((name, child), self.deps.edge_kinds(node, child));
+        }
+        let last = children.len().saturating_sub(1);
+        for (i, ((_, child), kinds)) in children.into_iter().enumerate() {
+            let (branch, indent) = if i == last {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+            self.print(
+                child,
+                &kinds,
+                &format!("{}{}", child_prefix, branch),
+                &format!("{}{}", child_prefix, indent),
+            );
+        }
+    }
+}
@@ -0,0 +1,829 @@
+use cargo::util::Sha256;
+use proc_macro2::{LineColumn, Span, TokenStream, TokenTree};
+use quote::ToTokens;
+use syn::visit::{self, Visit};
+use syn::{
+    Expr, ExprCall, ExprField, ExprMethodCall, ExprPath, ExprUnary, ExprUnsafe, FnDecl,
+    ForeignItemFn, ForeignItemStatic, ImplItemMethod, Item, ItemFn, ItemImpl, ItemMod, ItemStatic,
+    ItemTrait, ItemUnion, Macro, Member, Stmt, TraitItemMethod, UnOp,
+};
+
+use std::collections::HashSet;
+
+/// unsafe free functions of std, along with the module or type they're
+/// called through. they only count when called that way, like `ptr::read(p)`
+/// or `Box::from_raw(p)`, since there's no type information to go on and
+/// names like `read`, `write` and `add` are all over safe code.
+const STD_UNSAFE_FNS: &[(&str, &str)] = &[
+    ("alloc", "alloc"),
+    ("alloc", "alloc_zeroed"),
+    ("alloc", "dealloc"),
+    ("alloc", "realloc"),
+    ("hint", "unreachable_unchecked"),
+    ("mem", "transmute"),
+    ("mem", "transmute_copy"),
+    ("mem", "uninitialized"),
+    ("mem", "zeroed"),
+    ("ptr", "copy"),
+    ("ptr", "copy_nonoverlapping"),
+    ("ptr", "drop_in_place"),
+    ("ptr", "read"),
+    ("ptr", "read_unaligned"),
+    ("ptr", "read_volatile"),
+    ("ptr", "replace"),
+    ("ptr", "swap"),
+    ("ptr", "write"),
+    ("ptr", "write_bytes"),
+    ("ptr", "write_unaligned"),
+    ("ptr", "write_volatile"),
+    ("slice", "from_raw_parts"),
+    ("slice", "from_raw_parts_mut"),
+    ("str", "from_utf8_unchecked"),
+    ("str", "from_utf8_unchecked_mut"),
+    ("Arc", "from_raw"),
+    ("Box", "from_raw"),
+    ("CStr", "from_ptr"),
+    ("CString", "from_raw"),
+    ("NonNull", "new_unchecked"),
+    ("Pin", "new_unchecked"),
+    ("Rc", "from_raw"),
+    ("String", "from_raw_parts"),
+    ("String", "from_utf8_unchecked"),
+    ("Vec", "from_raw_parts"),
+];
+
+/// unsafe functions and methods of std whose names nothing safe uses, so
+/// that a call is taken to be one of them whatever it's called on, or
+/// however it was imported.
+const STD_UNSAFE_NAMES: &[&str] = &[
+    "assume_init",
+    "assume_init_drop",
+    "assume_init_read",
+    "copy_from_nonoverlapping",
+    "copy_nonoverlapping",
+    "copy_to_nonoverlapping",
+    "from_raw_parts",
+    "from_raw_parts_mut",
+    "from_utf8_unchecked",
+    "from_utf8_unchecked_mut",
+    "get_unchecked",
+    "get_unchecked_mut",
+    "offset_from",
+    "read_unaligned",
+    "read_volatile",
+    "set_len",
+    "slice_unchecked",
+    "transmute",
+    "transmute_copy",
+    "unreachable_unchecked",
+    "unwrap_unchecked",
+    "write_unaligned",
+    "write_volatile",
+];
+
+/// modules and prelude types of std with safe functions of their own, like
+/// `fs::write` or `String::from`, that a dependency's unsafe fn of the same
+/// name mustn't be mistaken for
+const STD_PATHS: &[&str] = &[
+    "alloc",
+    "any",
+    "ascii",
+    "cell",
+    "char",
+    "cmp",
+    "collections",
+    "convert",
+    "core",
+    "env",
+    "ffi",
+    "fmt",
+    "fs",
+    "hash",
+    "hint",
+    "io",
+    "iter",
+    "mem",
+    "net",
+    "num",
+    "ops",
+    "os",
+    "panic",
+    "path",
+    "process",
+    "ptr",
+    "slice",
+    "std",
+    "str",
+    "string",
+    "sync",
+    "thread",
+    "time",
+    "vec",
+    "Box",
+    "Option",
+    "Result",
+    "String",
+    "Vec",
+];
+
+/// the macros that expand to inline assembly
+const ASM_MACROS: &[&str] = &["asm", "llvm_asm", "global_asm"];
+
+/// where an unsafe site starts and ends within its file
+#[derive(Debug, Clone, Copy)]
+pub struct Location {
+    pub start: LineColumn,
+    pub end: LineColumn,
+}
+
+impl Location {
+    /// proc_macro2 can't join spans outside of nightly, so take the start of
+    /// the first token and the end of the last one instead
+    pub fn of<T: ToTokens>(node: &T) -> Option<Location> {
+        let tokens: Vec<TokenTree> = node.into_token_stream().into_iter().collect();
+        let first = tokens.first()?;
+        let last = tokens.last()?;
+        Some(Location {
+            start: first.span().start(),
+            end: last.span().end(),
+        })
+    }
+
+    /// from the start of `start` through to the end of `node`, e.g. the
+    /// `unsafe` keyword of a declaration up to its name
+    pub fn between<T: ToTokens>(start: Span, node: &T) -> Option<Location> {
+        let end = Location::of(node)?;
+        Some(Location {
+            start: start.start(),
+            end: end.end,
+        })
+    }
+}
+
+/// tells an unsafe site apart in a way that survives line numbers shifting:
+/// a hash of its tokens, which leaves out whitespace and comments, along with
+/// the path of the item it's in. the same code twice in one item gets the
+/// same fingerprint
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Fingerprint {
+    /// e.g. `fmt::<Buffer as Display>::fmt`, empty outside of any item
+    pub item: String,
+    /// 16 hex digits
+    pub hash: String,
+}
+
+/// the tokens of `node` as text, without the spaces proc_macro2 puts between
+/// them unless they keep two words apart, e.g. `Vec<u8>` rather than `Vec < u8 >`
+fn compact<T: ToTokens>(node: &T) -> String {
+    let chars: Vec<char> = node.into_token_stream().to_string().chars().collect();
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let mut text = String::new();
+    for (i, c) in chars.iter().enumerate() {
+        let between_words =
+            i > 0 && i + 1 < chars.len() && is_word(chars[i - 1]) && is_word(chars[i + 1]);
+        if *c != ' ' || between_words {
+            text.push(*c);
+        }
+    }
+    text
+}
+
+/// e.g. `<Buffer as Display>`, or just `Buffer` for an inherent impl
+fn impl_name(impl_def: &ItemImpl) -> String {
+    let self_ty = compact(&impl_def.self_ty);
+    match &impl_def.trait_ {
+        Some((_, path, _)) => format!("<{} as {}>", self_ty, compact(path)),
+        None => self_ty,
+    }
+}
+
+/// what the fingerprint of an unsafe fn goes by, along with its path: its
+/// generics, arguments and return type
+fn signature(decl: &FnDecl) -> TokenStream {
+    let mut tokens = TokenStream::new();
+    decl.generics.to_tokens(&mut tokens);
+    decl.inputs.to_tokens(&mut tokens);
+    decl.output.to_tokens(&mut tokens);
+    decl.generics.where_clause.to_tokens(&mut tokens);
+    tokens
+}
+
+/// the items that can be marked `unsafe` themselves, as opposed to holding
+/// unsafe code in their bodies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeclarationKind {
+    /// an `unsafe fn`, free standing or a method
+    Fn,
+    /// an `unsafe trait`
+    Trait,
+    /// an `unsafe impl`, promising the invariants of an unsafe trait hold
+    Impl,
+}
+
+impl DeclarationKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            DeclarationKind::Fn => "fn",
+            DeclarationKind::Trait => "trait",
+            DeclarationKind::Impl => "impl",
+        }
+    }
+}
+
+/// the operations that actually need `unsafe`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Category {
+    /// `*ptr`. any deref in an unsafe context is counted, since without types
+    /// a raw pointer looks just like a reference
+    RawPointerDeref,
+    /// a call to an `unsafe fn` or method, or to a foreign function
+    UnsafeCall,
+    /// reading or writing a `static mut`, or a foreign static
+    StaticMut,
+    /// reading a field of a `union`
+    UnionField,
+    /// `asm!` and friends
+    InlineAsm,
+    /// `unsafe impl` of an unsafe trait
+    UnsafeTraitImpl,
+}
+
+impl Category {
+    /// every category, in the order reports list them
+    pub const ALL: [Category; 6] = [
+        Category::RawPointerDeref,
+        Category::UnsafeCall,
+        Category::StaticMut,
+        Category::UnionField,
+        Category::InlineAsm,
+        Category::UnsafeTraitImpl,
+    ];
+
+    /// short stable name, used in reports
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::RawPointerDeref => "deref",
+            Category::UnsafeCall => "call",
+            Category::StaticMut => "static_mut",
+            Category::UnionField => "union_field",
+            Category::InlineAsm => "asm",
+            Category::UnsafeTraitImpl => "unsafe_impl",
+        }
+    }
+}
+
+/// names declared somewhere in a package whose use needs `unsafe`. this has
+/// to be gathered from every file of a package before walking any of them,
+/// since an unsafe fn can be called from a different file than it's declared in.
+#[derive(Debug, Default)]
+pub struct Declared {
+    unsafe_fns: HashSet<String>,
+    static_muts: HashSet<String>,
+    union_fields: HashSet<String>,
+    /// the unsafe free fns and foreign fns, which is what a dependent can
+    /// call by path
+    free_unsafe_fns: HashSet<String>,
+    /// the safe free fns, which calls by the same name as a dependency's
+    /// unsafe fn are more likely to mean
+    safe_fns: HashSet<String>,
+    /// the `free_unsafe_fns` of the package's dependencies
+    dependency_fns: HashSet<String>,
+}
+
+impl Declared {
+    /// start out knowing about the unsafe APIs of std that can be told apart
+    /// by name
+    pub fn new() -> Declared {
+        Declared {
+            unsafe_fns: STD_UNSAFE_NAMES
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            ..Declared::default()
+        }
+    }
+
+    pub fn collect(&mut self, file: &syn::File) {
+        DeclaredVisitor { declared: self }.visit_file(file);
+    }
+
+    /// the unsafe free fns and foreign fns declared in `files`, for their
+    /// package's dependents to know about
+    pub fn exported<'a, I: IntoIterator<Item = &'a syn::File>>(files: I) -> HashSet<String> {
+        let mut declared = Declared::default();
+        for file in files {
+            declared.collect(file);
+        }
+        declared.free_unsafe_fns
+    }
+
+    /// what the package's dependencies `exported`
+    pub fn add_dependency_fns(&mut self, fns: &HashSet<String>) {
+        self.dependency_fns.extend(fns.iter().cloned());
+    }
+
+    /// whether calling the fn at `path`, like `ptr::read` or `helper`, needs
+    /// `unsafe`
+    fn is_unsafe_fn(&self, path: &[String]) -> bool {
+        let name = match path.last() {
+            Some(name) => name,
+            None => return false,
+        };
+        if self.unsafe_fns.contains(name) {
+            return true;
+        }
+        if path.len() >= 2
+            && STD_UNSAFE_FNS.contains(&(path[path.len() - 2].as_str(), name.as_str()))
+        {
+            return true;
+        }
+        // like `libc::write(..)`, or `write(..)` after a `use`, but not
+        // `fs::write(..)` or a safe fn of the same name in this crate
+        let from_std = path[..path.len() - 1]
+            .iter()
+            .any(|segment| STD_PATHS.contains(&segment.as_str()));
+        let local = path.len() == 1 || ["self", "super", "crate"].contains(&path[0].as_str());
+        self.dependency_fns.contains(name) && !from_std && !(local && self.safe_fns.contains(name))
+    }
+}
+
+struct DeclaredVisitor<'a> {
+    declared: &'a mut Declared,
+}
+
+impl<'a, 'ast> Visit<'ast> for DeclaredVisitor<'a> {
+    fn visit_item_fn(&mut self, fn_def: &'ast ItemFn) {
+        let name = fn_def.ident.to_string();
+        if fn_def.unsafety.is_some() {
+            self.declared.unsafe_fns.insert(name.clone());
+            self.declared.free_unsafe_fns.insert(name);
+        } else {
+            self.declared.safe_fns.insert(name);
+        }
+        visit::visit_item_fn(self, fn_def);
+    }
+
+    fn visit_impl_item_method(&mut self, method_impl: &'ast ImplItemMethod) {
+        if method_impl.sig.unsafety.is_some() {
+            self.declared
+                .unsafe_fns
+                .insert(method_impl.sig.ident.to_string());
+        }
+        visit::visit_impl_item_method(self, method_impl);
+    }
+
+    fn visit_trait_item_method(&mut self, method_def: &'ast TraitItemMethod) {
+        if method_def.sig.unsafety.is_some() {
+            self.declared
+                .unsafe_fns
+                .insert(method_def.sig.ident.to_string());
+        }
+        visit::visit_trait_item_method(self, method_def);
+    }
+
+    fn visit_foreign_item_fn(&mut self, foreign_fn: &'ast ForeignItemFn) {
+        let name = foreign_fn.ident.to_string();
+        self.declared.unsafe_fns.insert(name.clone());
+        self.declared.free_unsafe_fns.insert(name);
+    }
+
+    fn visit_foreign_item_static(&mut self, foreign_static: &'ast ForeignItemStatic) {
+        self.declared
+            .static_muts
+            .insert(foreign_static.ident.to_string());
+    }
+
+    fn visit_item_static(&mut self, static_def: &'ast ItemStatic) {
+        if static_def.mutability.is_some() {
+            self.declared
+                .static_muts
+                .insert(static_def.ident.to_string());
+        }
+        visit::visit_item_static(self, static_def);
+    }
+
+    fn visit_item_union(&mut self, union_def: &'ast ItemUnion) {
+        for field in &union_def.fields.named {
+            if let Some(ident) = &field.ident {
+                self.declared.union_fields.insert(ident.to_string());
+            }
+        }
+        visit::visit_item_union(self, union_def);
+    }
+}
+
+/// everything unsafe the walker found in a single file
+#[derive(Debug, Default)]
+pub struct UnsafeSites {
+    /// statements inside an unsafe fn body or an unsafe block
+    pub lines: Vec<(Location, Fingerprint)>,
+    pub declarations: Vec<(DeclarationKind, Location, Fingerprint)>,
+    /// the operations that made an unsafe context necessary
+    pub operations: Vec<(Category, Location, Fingerprint)>,
+}
+
+/// walks every item, nested expression, block, match arm and closure body of a
+/// file, keeping track of whether we're inside an unsafe context
+struct UnsafeVisitor<'a> {
+    declared: &'a Declared,
+    in_unsafe_block: bool,
+    /// the module of the file followed by the items we're inside of
+    item_path: Vec<String>,
+    sites: UnsafeSites,
+}
+
+impl<'a> UnsafeVisitor<'a> {
+    fn new(declared: &'a Declared, module: &[String]) -> UnsafeVisitor<'a> {
+        UnsafeVisitor {
+            declared,
+            in_unsafe_block: false,
+            item_path: module.to_vec(),
+            sites: UnsafeSites::default(),
+        }
+    }
+
+    /// run `f` inside the item called `name`
+    fn with_item<F>(&mut self, name: String, f: F)
+    where
+        F: FnOnce(&mut Self),
+    {
+        self.item_path.push(name);
+        f(self);
+        self.item_path.pop();
+    }
+
+    /// `label` keeps different kinds of site on the same tokens apart
+    fn fingerprint<T: ToTokens>(&self, label: &str, node: &T) -> Fingerprint {
+        let item = self.item_path.join("::");
+        let mut hasher = Sha256::new();
+        for part in &[label, &item, &node.into_token_stream().to_string()] {
+            hasher.update(part.as_bytes());
+            hasher.update(&[0]);
+        }
+        let hash = hasher.finish()[..8]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        Fingerprint { item, hash }
+    }
+
+    /// run `f` with the unsafe context set to `in_unsafe_block`, restoring
+    /// whatever it was before once `f` is done
+    fn with_context<F>(&mut self, in_unsafe_block: bool, f: F)
+    where
+        F: FnOnce(&mut Self),
+    {
+        let outer = self.in_unsafe_block;
+        self.in_unsafe_block = in_unsafe_block;
+        f(self);
+        self.in_unsafe_block = outer;
+    }
+
+    /// `signature` is what the fingerprint goes by, beyond the item's path
+    fn declare<T: ToTokens>(
+        &mut self,
+        kind: DeclarationKind,
+        location: Option<Location>,
+        signature: &T,
+    ) {
+        if let Some(location) = location {
+            let fingerprint = self.fingerprint(kind.name(), signature);
+            self.sites.declarations.push((kind, location, fingerprint));
+        }
+    }
+
+    /// record `node` as an unsafe operation, as long as it's somewhere that
+    /// needs `unsafe` to begin with
+    fn operation<T: ToTokens>(&mut self, category: Category, node: &T) {
+        if self.in_unsafe_block {
+            self.push_operation(category, node);
+        }
+    }
+
+    fn push_operation<T: ToTokens>(&mut self, category: Category, node: &T) {
+        if let Some(location) = Location::of(node) {
+            let fingerprint = self.fingerprint(category.name(), node);
+            self.sites
+                .operations
+                .push((category, location, fingerprint));
+        }
+    }
+}
+
+/// a bare `{ ... }` or `unsafe { ... }` isn't a line of code by itself, the
+/// statements inside it get counted instead
+fn is_line(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::Expr(Expr::Block(_)) | Stmt::Semi(Expr::Block(_), _) => false,
+        Stmt::Expr(Expr::Unsafe(_)) | Stmt::Semi(Expr::Unsafe(_), _) => false,
+        Stmt::Item(Item::Macro(_)) => true,
+        Stmt::Item(_) => false,
+        _ => true,
+    }
+}
+
+impl<'a, 'ast> Visit<'ast> for UnsafeVisitor<'a> {
+    fn visit_item(&mut self, item: &'ast Item) {
+        // an item nested in an unsafe fn doesn't inherit its unsafety
+        self.with_context(false, |v| visit::visit_item(v, item));
+    }
+
+    fn visit_item_mod(&mut self, mod_def: &'ast ItemMod) {
+        self.with_item(mod_def.ident.to_string(), |v| {
+            visit::visit_item_mod(v, mod_def)
+        });
+    }
+
+    fn visit_item_fn(&mut self, fn_def: &'ast ItemFn) {
+        self.with_item(fn_def.ident.to_string(), |v| {
+            if let Some(unsafety) = fn_def.unsafety {
+                v.declare(
+                    DeclarationKind::Fn,
+                    Location::between(unsafety.span, &fn_def.ident),
+                    &signature(&fn_def.decl),
+                );
+            }
+            let in_unsafe_block = v.in_unsafe_block || fn_def.unsafety.is_some();
+            v.with_context(in_unsafe_block, |v| visit::visit_item_fn(v, fn_def));
+        });
+    }
+
+    fn visit_item_trait(&mut self, trait_def: &'ast ItemTrait) {
+        self.with_item(trait_def.ident.to_string(), |v| {
+            if let Some(unsafety) = trait_def.unsafety {
+                v.declare(
+                    DeclarationKind::Trait,
+                    Location::between(unsafety.span, &trait_def.ident),
+                    &trait_def.generics,
+                );
+            }
+            visit::visit_item_trait(v, trait_def);
+        });
+    }
+
+    fn visit_item_impl(&mut self, impl_def: &'ast ItemImpl) {
+        self.with_item(impl_name(impl_def), |v| {
+            // `unsafe impl` is a promise about the trait's invariants, it
+            // doesn't make the method bodies inside it unsafe
+            if let Some(unsafety) = impl_def.unsafety {
+                // the declaration and the operation are the same site, so
+                // they share a fingerprint
+                if let Some(location) = Location::between(unsafety.span, &impl_def.self_ty) {
+                    let fingerprint =
+                        v.fingerprint(DeclarationKind::Impl.name(), &impl_def.generics);
+                    v.sites.declarations.push((
+                        DeclarationKind::Impl,
+                        location,
+                        fingerprint.clone(),
+                    ));
+                    v.sites
+                        .operations
+                        .push((Category::UnsafeTraitImpl, location, fingerprint));
+                }
+            }
+            visit::visit_item_impl(v, impl_def);
+        });
+    }
+
+    fn visit_impl_item_method(&mut self, method_impl: &'ast ImplItemMethod) {
+        self.with_item(method_impl.sig.ident.to_string(), |v| {
+            if let Some(unsafety) = method_impl.sig.unsafety {
+                v.declare(
+                    DeclarationKind::Fn,
+                    Location::between(unsafety.span, &method_impl.sig.ident),
+                    &signature(&method_impl.sig.decl),
+                );
+            }
+            let in_unsafe_block = v.in_unsafe_block || method_impl.sig.unsafety.is_some();
+            v.with_context(in_unsafe_block, |v| {
+                visit::visit_impl_item_method(v, method_impl)
+            });
+        });
+    }
+
+    fn visit_trait_item_method(&mut self, method_def: &'ast TraitItemMethod) {
+        self.with_item(method_def.sig.ident.to_string(), |v| {
+            if let Some(unsafety) = method_def.sig.unsafety {
+                v.declare(
+                    DeclarationKind::Fn,
+                    Location::between(unsafety.span, &method_def.sig.ident),
+                    &signature(&method_def.sig.decl),
+                );
+            }
+            // default method bodies count just like any other method
+            let in_unsafe_block = v.in_unsafe_block || method_def.sig.unsafety.is_some();
+            v.with_context(in_unsafe_block, |v| {
+                visit::visit_trait_item_method(v, method_def)
+            });
+        });
+    }
+
+    fn visit_expr_unsafe(&mut self, expr: &'ast ExprUnsafe) {
+        self.with_context(true, |v| visit::visit_expr_unsafe(v, expr));
+    }
+
+    fn visit_expr_unary(&mut self, expr: &'ast ExprUnary) {
+        if let UnOp::Deref(_) = expr.op {
+            self.operation(Category::RawPointerDeref, expr);
+        }
+        visit::visit_expr_unary(self, expr);
+    }
+
+    fn visit_expr_call(&mut self, expr: &'ast ExprCall) {
+        if let Expr::Path(func) = &*expr.func {
+            let path: Vec<String> = func
+                .path
+                .segments
+                .iter()
+                .map(|s| s.ident.to_string())
+                .collect();
+            if self.declared.is_unsafe_fn(&path) {
+                self.operation(Category::UnsafeCall, expr);
+            }
+        }
+        visit::visit_expr_call(self, expr);
+    }
+
+    fn visit_expr_method_call(&mut self, expr: &'ast ExprMethodCall) {
+        if self.declared.unsafe_fns.contains(&expr.method.to_string()) {
+            self.operation(Category::UnsafeCall, expr);
+        }
+        visit::visit_expr_method_call(self, expr);
+    }
+
+    fn visit_expr_path(&mut self, expr: &'ast ExprPath) {
+        let name = expr
+            .path
+            .segments
+            .iter()
+            .last()
+            .map(|s| s.ident.to_string());
+        if name.is_some_and(|name| self.declared.static_muts.contains(&name)) {
+            self.operation(Category::StaticMut, expr);
+        }
+        visit::visit_expr_path(self, expr);
+    }
+
+    fn visit_expr_field(&mut self, expr: &'ast ExprField) {
+        if let Member::Named(ident) = &expr.member {
+            if self.declared.union_fields.contains(&ident.to_string()) {
+                self.operation(Category::UnionField, expr);
+            }
+        }
+        visit::visit_expr_field(self, expr);
+    }
+
+    fn visit_macro(&mut self, mac: &'ast Macro) {
+        let name = mac.path.segments.iter().last().map(|s| s.ident.to_string());
+        if name.is_some_and(|name| ASM_MACROS.contains(&name.as_str())) {
+            // global_asm! lives outside of any function, so doesn't wait for
+            // an unsafe context
+            self.push_operation(Category::InlineAsm, mac);
+        }
+        visit::visit_macro(self, mac);
+    }
+
+    fn visit_stmt(&mut self, stmt: &'ast Stmt) {
+        if self.in_unsafe_block && is_line(stmt) {
+            if let Some(location) = Location::of(stmt) {
+                let fingerprint = self.fingerprint("line", stmt);
+                self.sites.lines.push((location, fingerprint));
+            }
+        }
+        visit::visit_stmt(self, stmt);
+    }
+}
+
+/// find the unsafe declarations of a file, the statements that sit inside an
+/// `unsafe fn` or an `unsafe` block however deeply they're nested, and the
+/// operations in there that needed it. statics, consts and trait default
+/// methods get walked like everything else. `module` is the module the file
+/// holds, which the fingerprints of its sites start from.
+pub fn unsafe_sites_of_file(
+    file: &syn::File,
+    module: &[String],
+    declared: &Declared,
+) -> UnsafeSites {
+    let mut visitor = UnsafeVisitor::new(declared, module);
+    visitor.visit_file(file);
+    visitor.sites
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the sites of `source` walked as a file of its own, knowing about
+    /// `dependency_fns` from its dependencies
+    fn sites_of(source: &str, dependency_fns: &[&str]) -> UnsafeSites {
+        let file = syn::parse_file(source).unwrap();
+        let mut declared = Declared::new();
+        declared.collect(&file);
+        declared.add_dependency_fns(&dependency_fns.iter().map(|f| f.to_string()).collect());
+        unsafe_sites_of_file(&file, &[], &declared)
+    }
+
+    /// the lines of the operations of `category`, 1-based
+    fn lines_of(sites: &UnsafeSites, category: Category) -> Vec<usize> {
+        sites
+            .operations
+            .iter()
+            .filter(|(c, _, _)| *c == category)
+            .map(|(_, location, _)| location.start.line)
+            .collect()
+    }
+
+    #[test]
+    fn safe_lookalikes_of_std_fns_are_not_calls() {
+        let sites = sites_of(
+            include_str!("../regression_examples/safe_lookalikes.rs"),
+            &[],
+        );
+        // `std::ptr::read(p)` and the call to `lookalikes`
+        assert_eq!(lines_of(&sites, Category::UnsafeCall), vec![14, 24]);
+    }
+
+    #[test]
+    fn dependency_fns_are_calls_unless_std_or_local() {
+        let sites = sites_of(
+            "fn close(fd: i32) {}
+            fn main() {
+                unsafe {
+                    libc::write(1, p, 1);
+                    write(1, p, 1);
+                    std::fs::write(path, data);
+                    fs::write(path, data);
+                    close(1);
+                    libc::close(1);
+                }
+            }",
+            &["write", "close"],
+        );
+        assert_eq!(lines_of(&sites, Category::UnsafeCall), vec![4, 5, 9]);
+    }
+
+    #[test]
+    fn unsafe_blocks_nested_in_control_flow_and_closures_are_found() {
+        let sites = sites_of(
+            "fn f(ptrs: &[*const u8], first: Option<*const u8>) -> u8 {
+                for &p in ptrs {
+                    let _ = unsafe { *p };
+                }
+                let n = match first {
+                    Some(p) => unsafe { *p },
+                    None => 0,
+                };
+                let read = |p: *const u8| unsafe { *p };
+                while n > 0 {
+                    loop {
+                        unsafe { *ptrs[0] };
+                    }
+                }
+                let _ = async { unsafe { *ptrs[1] } };
+                read(ptrs[2])
+            }",
+            &[],
+        );
+        assert_eq!(
+            lines_of(&sites, Category::RawPointerDeref),
+            vec![3, 6, 9, 12, 15]
+        );
+        assert!(sites.declarations.is_empty());
+    }
+
+    #[test]
+    fn unsafe_items_are_declarations_apart_from_unsafe_bodies() {
+        let sites = sites_of(
+            "struct S(*const u8);
+            unsafe impl Send for S {}
+            unsafe trait Marker {
+                fn get(&self) -> u8;
+            }
+            unsafe impl Marker for S {
+                fn get(&self) -> u8 { *self.0 }
+            }
+            impl S {
+                unsafe fn raw(&self) -> u8 { *self.0 }
+            }
+            static FIRST: u8 = unsafe { *P };
+            const SECOND: u8 = unsafe { *P };",
+            &[],
+        );
+        let declarations: Vec<(&str, usize)> = sites
+            .declarations
+            .iter()
+            .map(|(kind, location, _)| (kind.name(), location.start.line))
+            .collect();
+        assert_eq!(
+            declarations,
+            vec![("impl", 2), ("trait", 3), ("impl", 6), ("fn", 10)]
+        );
+        // the body of a method in an `unsafe impl` isn't unsafe, but statics
+        // and consts get walked
+        assert_eq!(
+            lines_of(&sites, Category::RawPointerDeref),
+            vec![10, 12, 13]
+        );
+        assert_eq!(lines_of(&sites, Category::UnsafeTraitImpl), vec![2, 6]);
+    }
+}
@@ -0,0 +1,220 @@
+use petgraph::graph::NodeIndex;
+use petgraph::Direction;
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::deps::{edge_tag, DepGraph};
+use crate::Result;
+
+/// prints the paths to every package called `name`, as `print_paths` does
+pub fn print_why(
+    deps: &DepGraph,
+    name: &str,
+    own: &HashMap<NodeIndex, usize>,
+    limit: Option<usize>,
+) -> Result<()> {
+    let nodes: Vec<NodeIndex> = deps
+        .graph
+        .node_indices()
+        .filter(|node| deps.graph[*node].name().as_str() == name)
+        .collect();
+    if nodes.is_empty() {
+        return Err(format!("{} isn't in the dependency graph", name).into());
+    }
+    for node in nodes {
+        print_paths(deps, node, own, limit);
+    }
+    Ok(())
+}
+
+/// prints every path from a root down to `node`, shortest first, with the
+/// unsafe lines of each package along the way and of its subtree, to tell
+/// which of the roots' dependencies brings `node` in. with a `limit` there
+/// can be fewer, but every dependency of a root that leads to `node` still
+/// gets its shortest path printed, and the number of paths left out follows
+fn print_paths(
+    deps: &DepGraph,
+    node: NodeIndex,
+    own: &HashMap<NodeIndex, usize>,
+    limit: Option<usize>,
+) {
+    let mut counts = HashMap::new();
+    let (total, _) = count_paths(deps, node, &mut counts);
+    let mut paths = match limit {
+        Some(_) => first_hops(deps, node),
+        None => vec![],
+    };
+    paths_to_root(deps, &counts, limit, &mut vec![node], &mut paths);
+    // shortest first, and then by the names down from the root
+    paths.sort_by_key(|path| {
+        let names: Vec<String> = path
+            .iter()
+            .rev()
+            .map(|node| deps.graph[*node].name().to_string())
+            .collect();
+        (path.len(), names)
+    });
+
+    let package = &deps.graph[node];
+    println!(
+        "{} v{} ({} unsafe, {} in subtree) is reached through {} path{}",
+        package.name(),
+        package.version(),
+        own.get(&node).cloned().unwrap_or(0),
+        deps.subtree_total(node, own),
+        total,
+        if total == 1 { "" } else { "s" }
+    );
+    let shown = paths.len();
+    for path in paths {
+        let mut steps = vec![];
+        let mut parent = None;
+        for node in path.into_iter().rev() {
+            let package = &deps.graph[node];
+            let kinds = match parent {
+                Some(parent) => edge_tag(&deps.edge_kinds(parent, node)),
+                None => String::new(),
+            };
+            steps.push(format!(
+                "{} v{}{} ({} unsafe, {} in subtree)",
+                package.name(),
+                package.version(),
+                kinds,
+                own.get(&node).cloned().unwrap_or(0),
+                deps.subtree_total(node, own)
+            ));
+            parent = Some(node);
+        }
+        println!("  {}", steps.join(" -> "));
+    }
+    if total > shown {
+        println!("  and {} more", total - shown);
+    }
+}
+
+/// the packages depending on `node`, once each even when there's more than
+/// one edge between them
+fn parents(deps: &DepGraph, node: NodeIndex) -> Vec<NodeIndex> {
+    let mut parents: Vec<NodeIndex> = deps
+        .graph
+        .neighbors_directed(node, Direction::Incoming)
+        .collect();
+    parents.sort();
+    parents.dedup();
+    parents
+}
+
+/// how many paths go from a root down to `node` and how long the shortest
+/// one is, worked out once per package into `counts` rather than by walking
+/// every path. a path stops at the first root it gets to, which is also what
+/// breaks the cycles dev dependencies can make
+fn count_paths(
+    deps: &DepGraph,
+    node: NodeIndex,
+    counts: &mut HashMap<NodeIndex, (usize, usize)>,
+) -> (usize, usize) {
+    if let Some(count) = counts.get(&node) {
+        return *count;
+    }
+    if deps.roots.contains(&node) {
+        counts.insert(node, (1, 0));
+        return (1, 0);
+    }
+    // anything else that gets back here while it's being counted is on a
+    // cycle, and no path goes through a package twice
+    counts.insert(node, (0, usize::MAX));
+    let mut total: usize = 0;
+    let mut shortest = usize::MAX;
+    for parent in parents(deps, node) {
+        let (count, length) = count_paths(deps, parent, counts);
+        total = total.saturating_add(count);
+        shortest = shortest.min(length.saturating_add(1));
+    }
+    counts.insert(node, (total, shortest));
+    (total, shortest)
+}
+
+/// the shortest path from `node` up to each root through each of the root's
+/// dependencies that leads to it, so that a limit never hides which direct
+/// dependency to replace
+fn first_hops(deps: &DepGraph, node: NodeIndex) -> Vec<Vec<NodeIndex>> {
+    // breadth first up from `node`, remembering the way back down. a path
+    // stops at the first root it gets to
+    if deps.roots.contains(&node) {
+        return vec![];
+    }
+    let mut toward: HashMap<NodeIndex, NodeIndex> = HashMap::new();
+    let mut to_check = VecDeque::new();
+    to_check.push_back(node);
+    while let Some(next) = to_check.pop_front() {
+        if next != node && deps.roots.contains(&next) {
+            continue;
+        }
+        for parent in parents(deps, next) {
+            if parent != node && !toward.contains_key(&parent) {
+                toward.insert(parent, next);
+                to_check.push_back(parent);
+            }
+        }
+    }
+    let mut paths = vec![];
+    for root in &deps.roots {
+        if !toward.contains_key(root) {
+            continue;
+        }
+        let mut children: Vec<NodeIndex> = deps.graph.neighbors(*root).collect();
+        children.sort();
+        children.dedup();
+        for child in children {
+            if child != node && (deps.roots.contains(&child) || !toward.contains_key(&child)) {
+                continue;
+            }
+            let mut path = vec![*root];
+            let mut next = child;
+            path.push(next);
+            while next != node {
+                next = toward[&next];
+                path.push(next);
+            }
+            path.reverse();
+            paths.push(path);
+        }
+    }
+    paths
+}
+
+/// extends `path`, which goes from a package up towards the roots, with every
+/// way of getting to one from its last package, or until `paths` has `limit`
+/// of them. the parent closest to a root goes first, and paths already in
+/// `paths` aren't added again. dev dependencies can make cycles, so a path
+/// never visits a package twice
+fn paths_to_root(
+    deps: &DepGraph,
+    counts: &HashMap<NodeIndex, (usize, usize)>,
+    limit: Option<usize>,
+    path: &mut Vec<NodeIndex>,
+    paths: &mut Vec<Vec<NodeIndex>>,
+) {
+    let node = *path.last().unwrap();
+    if deps.roots.contains(&node) {
+        // only a limit starts `paths` off with some already
+        if limit.is_none() || !paths.contains(path) {
+            paths.push(path.clone());
+        }
+        return;
+    }
+    let mut parents = parents(deps, node);
+    parents.retain(|parent| counts.get(parent).is_some_and(|(count, _)| *count > 0));
+    parents.sort_by_key(|parent| counts[parent].1);
+    for parent in parents {
+        if limit.is_some_and(|limit| paths.len() >= limit) {
+            return;
+        }
+        if path.contains(&parent) {
+            continue;
+        }
+        path.push(parent);
+        paths_to_root(deps, counts, limit, path, paths);
+        path.pop();
+    }
+}